@@ -0,0 +1,192 @@
+//! A* pathfinding on a [Grid], the tiles decide where you can go and what it costs.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{Pos, grids::Grid};
+
+const DIRECTIONS: [(i32, i32); 8] =
+    [(-1, 0), (1, 0), (0, -1), (0, 1), (1, -1), (-1, -1), (1, 1), (-1, 1)];
+
+/// Which diagonal steps are allowed, judged by the two orthogonal tiles the step passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagonals {
+    /// Only orthogonal steps
+    Never,
+    Always,
+    /// Both tiles next to the step have to be passable
+    NoCornerCutting,
+    /// One of the tiles next to the step has to be passable, no squeezing between two walls
+    NoSqueezing,
+}
+
+impl Diagonals {
+    fn allows(self, from: Pos, (dx, dy): (i32, i32), open: impl Fn(Pos) -> bool) -> bool {
+        if dx == 0 || dy == 0 {
+            return true;
+        }
+        match self {
+            Diagonals::Never => false,
+            Diagonals::Always => true,
+            Diagonals::NoCornerCutting => open(from + (dx, 0)) && open(from + (0, dy)),
+            Diagonals::NoSqueezing => open(from + (dx, 0)) || open(from + (0, dy)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PathOptions {
+    pub diagonals: Diagonals,
+    /// Paths with more steps than this are not considered
+    pub max_length: Option<i32>,
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        PathOptions { diagonals: Diagonals::NoCornerCutting, max_length: None }
+    }
+}
+
+/// Cheapest path from `start` to `goal`, both included.
+/// `cost` is paid for entering a tile and has to be at least 1, otherwise the path
+/// may not be the cheapest one.
+/// Tiles for which `passable` is false are never entered, that includes the goal.
+pub fn astar<T: Clone>(
+    grid: &Grid<T>,
+    start: Pos,
+    goal: Pos,
+    options: PathOptions,
+    passable: impl Fn(Pos, &T) -> bool,
+    cost: impl Fn(Pos, &T) -> i32,
+) -> Option<Vec<Pos>> {
+    grid.get_opt(start)?;
+    grid.get_opt(goal)?;
+    let heuristic = |pos: Pos| match options.diagonals {
+        Diagonals::Never => pos.distance_manhattan(goal),
+        _ => pos.distance(goal),
+    };
+    let open = |pos: Pos| grid.get_opt(pos).is_some_and(|tile| passable(pos, tile));
+
+    let (width, height) = (grid.width, grid.height);
+    let mut costs = Grid::new(width, height, i32::MAX);
+    let mut steps = Grid::new(width, height, 0);
+    let mut came_from = Grid::new(width, height, None::<Pos>);
+    let mut done = Grid::new(width, height, false);
+    let mut todo = BinaryHeap::new();
+    costs[start] = 0;
+    todo.push(Reverse((heuristic(start), start)));
+
+    while let Some(Reverse((_, current))) = todo.pop() {
+        if current == goal {
+            break;
+        }
+        if done[current] || options.max_length.is_some_and(|max| steps[current] >= max) {
+            continue;
+        }
+        done[current] = true;
+
+        for dir in DIRECTIONS {
+            let next = current + dir;
+            let Some(tile) = grid.get_opt(next) else { continue };
+            if !passable(next, tile) || !options.diagonals.allows(current, dir, open) {
+                continue;
+            }
+            let new_cost = costs[current].saturating_add(cost(next, tile));
+            if new_cost < costs[next] {
+                costs[next] = new_cost;
+                steps[next] = steps[current] + 1;
+                came_from[next] = Some(current);
+                todo.push(Reverse((new_cost.saturating_add(heuristic(next)), next)));
+            }
+        }
+    }
+
+    if start != goal && came_from[goal].is_none() {
+        return None;
+    }
+    let mut path = vec![goal];
+    let mut pos = goal;
+    while let Some(previous) = came_from[pos] {
+        path.push(previous);
+        pos = previous;
+    }
+    path.reverse();
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `#` is a wall, digits are the cost of entering, everything else costs 1.
+    fn grid(rows: &[&str]) -> Grid<char> {
+        Grid::filled_with(rows[0].len() as i32, rows.len() as i32, |x, y| {
+            rows[y as usize].as_bytes()[x as usize] as char
+        })
+    }
+
+    fn path(rows: &[&str], start: Pos, goal: Pos, options: PathOptions) -> Option<Vec<Pos>> {
+        let passable = |_, tile: &char| *tile != '#';
+        let cost = |_, tile: &char| tile.to_digit(10).unwrap_or(1) as i32;
+        astar(&grid(rows), start, goal, options, passable, cost)
+    }
+
+    #[test]
+    fn astar_test() {
+        let (start, goal) = (Pos::new(0, 0), Pos::new(4, 0));
+
+        // basic
+        let open = ["....."];
+        let found = path(&open, start, goal, PathOptions::default()).unwrap();
+        assert_eq!(5, found.len());
+        assert_eq!(Some(&start), found.first());
+        assert_eq!(Some(&goal), found.last());
+        assert_eq!(Some(vec![start]), path(&open, start, start, PathOptions::default()));
+
+        // around a wall
+        let wall = [".#...", ".#.#.", "...#."];
+        let found = path(&wall, start, goal, PathOptions::default()).unwrap();
+        assert!(found.windows(2).all(|step| step[0].distance(step[1]) == 1));
+        assert!(found.iter().all(|pos| grid(&wall)[*pos] != '#'));
+
+        // no way through
+        let blocked = ["..#..", "..#..", "..#.."];
+        assert_eq!(None, path(&blocked, start, goal, PathOptions::default()));
+        assert_eq!(None, path(&blocked, start, Pos::new(2, 0), PathOptions::default()));
+
+        // expensive tiles are avoided
+        let mud = [".999.", "....."];
+        let found = path(&mud, start, goal, PathOptions::default()).unwrap();
+        assert!(found.iter().all(|pos| pos.y == 1 || pos.x == 0 || pos.x == 4));
+
+        // max length
+        let options = PathOptions { max_length: Some(3), ..PathOptions::default() };
+        assert_eq!(None, path(&open, start, goal, options));
+        let options = PathOptions { max_length: Some(4), ..PathOptions::default() };
+        assert!(path(&open, start, goal, options).is_some());
+    }
+
+    #[test]
+    fn diagonals_test() {
+        let corner = ["..", "#."];
+        let (start, goal) = (Pos::new(0, 0), Pos::new(1, 1));
+        let with = |diagonals| PathOptions { diagonals, max_length: None };
+
+        assert_eq!(2, path(&corner, start, goal, with(Diagonals::Always)).unwrap().len());
+        assert_eq!(
+            3,
+            path(&corner, start, goal, with(Diagonals::NoCornerCutting)).unwrap().len()
+        );
+        assert_eq!(2, path(&corner, start, goal, with(Diagonals::NoSqueezing)).unwrap().len());
+        assert_eq!(3, path(&corner, start, goal, with(Diagonals::Never)).unwrap().len());
+
+        let squeeze = [".#", "#."];
+        assert_eq!(2, path(&squeeze, start, goal, with(Diagonals::Always)).unwrap().len());
+        assert_eq!(None, path(&squeeze, start, goal, with(Diagonals::NoSqueezing)));
+        assert_eq!(None, path(&squeeze, start, goal, with(Diagonals::NoCornerCutting)));
+
+        let open = ["...", "...", "..."];
+        let found = path(&open, start, Pos::new(2, 2), with(Diagonals::Never)).unwrap();
+        assert_eq!(5, found.len());
+        assert!(found.windows(2).all(|step| step[0].distance_manhattan(step[1]) == 1));
+    }
+}
@@ -1,6 +1,7 @@
 // vendored
 #![allow(dead_code)]
 use std::{
+    collections::VecDeque,
     mem::offset_of,
     ops::{Add, Index, IndexMut, Mul},
 };
@@ -102,16 +103,21 @@ impl<T: Clone> Grid<T> {
         }
     }
 
+    /// Iterates column by column over the tiles from `from` up to `to` exclusive.
+    /// The parts of the rect outside of the grid are skipped instead of panicking,
+    /// so callers can pass rects around a position without clamping them first.
     pub fn iter_rect(&self, from: Pos, to: Pos) -> impl Iterator<Item = (i32, i32, &T)> {
-        let mut coords = vec![];
-
-        for x in from.x..to.x {
-            for y in from.y..to.y {
-                coords.push((x, y));
-            }
-        }
+        let (x_min, x_max) = (from.x.max(0), to.x.min(self.width));
+        let (y_min, y_max) = (from.y.max(0), to.y.min(self.height));
+        (x_min..x_max).flat_map(move |x| (y_min..y_max).map(move |y| (x, y, self.get(x, y))))
+    }
 
-        coords.into_iter().map(|(x, y)| (x, y, &self[(x, y)]))
+    /// The values at `positions`, positions outside of the grid are skipped.
+    pub fn iter_region(
+        &self,
+        positions: impl IntoIterator<Item = Pos>,
+    ) -> impl Iterator<Item = (Pos, &T)> {
+        positions.into_iter().filter_map(|pos| Some((pos, self.get_opt(pos)?)))
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &T)> {
@@ -136,7 +142,7 @@ impl<T: Clone> Grid<T> {
         self.data.iter_mut().enumerate().map(|(i, v)| {
             let i = i as i32;
             let x = i % self.width;
-            let y = i / self.height;
+            let y = i / self.width;
 
             (x, y, v)
         })
@@ -315,6 +321,118 @@ impl<T: Clone> Grid<T> {
     }
 }
 
+/// Connected groups of tiles, see [Grid::components].
+#[derive(Debug, Clone)]
+pub struct Components {
+    /// Which component each tile belongs to, `None` for tiles that didn't match
+    pub labels: Grid<Option<usize>>,
+    /// Number of tiles in each component
+    pub sizes: Vec<usize>,
+}
+
+impl Components {
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    /// Label of the component with the most tiles
+    pub fn largest(&self) -> Option<usize> {
+        (0..self.len()).max_by_key(|label| self.sizes[*label])
+    }
+
+    /// The tiles of each component, indexed by label.
+    pub fn regions(&self) -> Vec<Vec<Pos>> {
+        let mut regions: Vec<Vec<Pos>> =
+            self.sizes.iter().map(|size| Vec::with_capacity(*size)).collect();
+        for (pos, label) in self.labels.iter_coords() {
+            if let Some(label) = label {
+                regions[*label].push(pos);
+            }
+        }
+        regions
+    }
+}
+
+/// Region algorithms, all of them only step orthogonally unless noted otherwise.
+impl<T: Clone> Grid<T> {
+    /// All tiles connected to `start` for which `pred` is true.
+    /// Empty if `start` itself doesn't match or is outside the grid.
+    pub fn flood_fill(&self, start: Pos, pred: impl Fn(Pos, &T) -> bool) -> Vec<Pos> {
+        let mut seen = Grid::new(self.width, self.height, false);
+        self.fill_from(start, &pred, &mut seen)
+    }
+
+    fn fill_from(
+        &self,
+        start: Pos,
+        pred: &impl Fn(Pos, &T) -> bool,
+        seen: &mut Grid<bool>,
+    ) -> Vec<Pos> {
+        let mut region = Vec::new();
+        if !self.get_opt(start).is_some_and(|v| pred(start, v)) || seen[start] {
+            return region;
+        }
+        let mut todo = vec![start];
+        seen[start] = true;
+        while let Some(pos) = todo.pop() {
+            region.push(pos);
+            for next in pos.neighbors_orth(self) {
+                if !seen[next] && pred(next, &self[next]) {
+                    seen[next] = true;
+                    todo.push(next);
+                }
+            }
+        }
+        region
+    }
+
+    /// Labels every group of connected tiles for which `pred` is true.
+    /// Labels are handed out in the order the groups are first found, row by row.
+    pub fn components(&self, pred: impl Fn(Pos, &T) -> bool) -> Components {
+        let mut seen = Grid::new(self.width, self.height, false);
+        let mut labels = Grid::new(self.width, self.height, None);
+        let mut sizes = Vec::new();
+        for start in self.coords() {
+            let region = self.fill_from(start, &pred, &mut seen);
+            if region.is_empty() {
+                continue;
+            }
+            for pos in &region {
+                labels[*pos] = Some(sizes.len());
+            }
+            sizes.push(region.len());
+        }
+        Components { labels, sizes }
+    }
+
+    /// Steps to the nearest tile for which `is_wall` is true, walking in 8 directions.
+    /// Walls are 0, the outside of the grid doesn't count as wall.
+    /// Everything is `i32::MAX` if there are no walls.
+    pub fn distance_transform(&self, is_wall: impl Fn(Pos, &T) -> bool) -> Grid<i32> {
+        let mut distances = Grid::new(self.width, self.height, i32::MAX);
+        let mut todo = VecDeque::new();
+        for (pos, v) in self.iter_coords() {
+            if is_wall(pos, v) {
+                distances[pos] = 0;
+                todo.push_back(pos);
+            }
+        }
+        while let Some(pos) = todo.pop_front() {
+            for next in pos.neighbors(self) {
+                if distances[next] == i32::MAX {
+                    distances[next] = distances[pos] + 1;
+                    todo.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+}
+
 impl<T: Clone, Pos: Into<(i32, i32)>> Index<Pos> for Grid<T> {
     type Output = T;
 
@@ -489,3 +607,99 @@ fn test_grid_add() {
     let c = a.add(&b);
     assert_eq!(3.5, c[(2, 2)]);
 }
+
+#[test]
+fn test_iter_mut_non_square() {
+    // wider than high, so dividing by the height would give wrong rows
+    let mut grid = Grid::new(4, 2, Pos::new(0, 0));
+    for (x, y, v) in grid.iter_mut() {
+        *v = Pos::new(x, y);
+    }
+    for (pos, v) in grid.iter_coords() {
+        assert_eq!(pos, *v);
+    }
+    for (pos, v) in grid.iter_coords_mut() {
+        assert_eq!(pos, *v);
+    }
+}
+
+#[cfg(test)]
+/// `#` is true, everything else false
+fn grid(rows: &[&str]) -> Grid<bool> {
+    Grid::filled_with(rows[0].len() as i32, rows.len() as i32, |x, y| {
+        rows[y as usize].as_bytes()[x as usize] == b'#'
+    })
+}
+
+#[test]
+fn rect_and_region_queries() {
+    let g = Grid::filled_with(3, 2, |x, y| x + 10 * y);
+    let values: Vec<i32> = g.iter_rect(Pos::new(1, 0), Pos::new(3, 2)).map(|v| *v.2).collect();
+    assert_eq!(vec![1, 11, 2, 12], values);
+
+    let positions = [Pos::new(2, 1), Pos::new(-1, 0), Pos::new(0, 2)];
+    let found: Vec<_> = g.iter_region(positions).map(|(pos, v)| (pos, *v)).collect();
+    assert_eq!(vec![(Pos::new(2, 1), 12)], found);
+}
+
+#[test]
+fn iter_rect_clips_to_grid() {
+    // rects reaching outside of the grid used to panic, now only the inside is visited
+    let g = Grid::filled_with(3, 2, |x, y| x + 10 * y);
+    assert_eq!(6, g.iter_rect(Pos::new(-5, -5), Pos::new(10, 10)).count());
+    assert_eq!(0, g.iter_rect(Pos::new(3, 0), Pos::new(5, 2)).count());
+    let corner: Vec<_> = g.iter_rect(Pos::new(2, 1), Pos::new(4, 3)).collect();
+    assert_eq!(vec![(2, 1, &12)], corner);
+}
+
+#[test]
+fn flood_fill_test() {
+    let g = grid(&["##..", "#..#", "..##"]);
+    let wall = |_, v: &bool| *v;
+
+    let mut top_left = g.flood_fill(Pos::new(0, 0), wall);
+    top_left.sort();
+    assert_eq!(vec![Pos::new(0, 0), Pos::new(0, 1), Pos::new(1, 0)], top_left);
+    assert_eq!(3, g.flood_fill(Pos::new(3, 1), wall).len());
+    // no diagonal steps
+    assert_eq!(vec![Pos::new(0, 0)], grid(&["#.", ".#"]).flood_fill(Pos::new(0, 0), wall));
+    assert!(g.flood_fill(Pos::new(2, 0), wall).is_empty());
+    assert!(g.flood_fill(Pos::new(-1, 0), wall).is_empty());
+    assert!(g.flood_fill(Pos::new(4, 0), wall).is_empty());
+}
+
+#[test]
+fn components_test() {
+    let g = grid(&["##..", "#..#", "..##"]);
+    let walls = g.components(|_, v| *v);
+    assert_eq!(2, walls.len());
+    assert_eq!(vec![3, 3], walls.sizes);
+    assert_eq!(Some(0), walls.labels[Pos::new(1, 0)]);
+    assert_eq!(Some(1), walls.labels[Pos::new(3, 1)]);
+    assert_eq!(Some(1), walls.labels[Pos::new(2, 2)]);
+    assert_eq!(None, walls.labels[Pos::new(2, 0)]);
+
+    let floors = g.components(|_, v| !*v);
+    assert_eq!(1, floors.len());
+    assert_eq!(Some(0), floors.largest());
+    assert_eq!(6, floors.regions()[0].len());
+
+    let none = g.components(|_, _| false);
+    assert!(none.is_empty());
+    assert_eq!(None, none.largest());
+}
+
+#[test]
+fn distance_transform_test() {
+    let g = grid(&["#....", ".....", "....."]);
+    let d = g.distance_transform(|_, v| *v);
+    assert_eq!(0, d[Pos::new(0, 0)]);
+    assert_eq!(1, d[Pos::new(1, 1)]);
+    assert_eq!(2, d[Pos::new(2, 2)]);
+    // the grid edge isn't a wall
+    assert_eq!(4, d[Pos::new(4, 0)]);
+
+    let empty = grid(&["...", "..."]);
+    let d = empty.distance_transform(|_, v| *v);
+    assert!(d.iter_values().all(|v| *v == i32::MAX));
+}
@@ -7,13 +7,16 @@ pub use tracy;
 
 #[macro_use]
 pub mod profile_util;
+pub mod astar;
 pub mod circle;
 pub mod grids;
 pub mod input;
+pub mod los;
 pub mod pos;
 pub mod rational;
 pub mod rect;
 pub mod shadowcasting;
+pub mod shapes;
 pub mod text;
 pub mod util;
 
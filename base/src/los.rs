@@ -0,0 +1,195 @@
+//! Line of sight and line of fire between two positions.
+//! Line of sight agrees with [compute_fov](crate::shadowcasting::compute_fov),
+//! so whatever the player sees can also be targeted and the other way around.
+
+use std::collections::HashSet;
+
+use crate::{Pos, shadowcasting};
+
+/// True if `to` is visible from `from`.
+/// Symmetric for tiles that don't block, walls are seen but can't see.
+pub fn has_los(from: Pos, to: Pos, blocks_vision: impl Fn(Pos) -> bool) -> bool {
+    let reach = from.distance(to);
+    let mut seen = false;
+    shadowcasting::compute_fov(
+        from,
+        // rows further away than the target don't matter for it
+        &mut |p| from.distance(p) > reach || blocks_vision(p),
+        &mut |p| seen |= p == to,
+    );
+    seen
+}
+
+/// All tiles visible from `from` that are at most `reach` away.
+pub fn visible_within(
+    from: Pos,
+    reach: i32,
+    blocks_vision: impl Fn(Pos) -> bool,
+) -> HashSet<Pos> {
+    let mut seen = HashSet::new();
+    shadowcasting::compute_fov(
+        from,
+        &mut |p| from.distance(p) > reach || blocks_vision(p),
+        &mut |p| {
+            if from.distance(p) <= reach {
+                seen.insert(p);
+            }
+        },
+    );
+    seen
+}
+
+/// The first position after the start of `line` for which `blocked` is true.
+pub fn first_blocker(line: &[Pos], blocked: impl Fn(Pos) -> bool) -> Option<Pos> {
+    line.iter().skip(1).copied().find(|p| blocked(*p))
+}
+
+/// The line from `from` to `to` a projectile should take, both ends included.
+/// Tries the lines shadowcasting could have seen `to` along and picks the first one
+/// where nothing but `to` itself is in the way, so `to` can be hit whenever it is in sight.
+/// If every line is blocked, the one that gets furthest is returned.
+pub fn line_of_fire(from: Pos, to: Pos, blocked: impl Fn(Pos) -> bool) -> Vec<Pos> {
+    let candidates = [center_ray(from, to, true), center_ray(from, to, false)];
+
+    let reached = |line: &[Pos]| match first_blocker(line, &blocked) {
+        None => line.len(),
+        Some(p) if p == to => line.len(),
+        Some(p) => line.iter().position(|q| *q == p).unwrap(),
+    };
+    let mut best = 0;
+    for (i, line) in candidates.iter().enumerate() {
+        let r = reached(line);
+        if r == line.len() {
+            best = i;
+            break;
+        }
+        if r > reached(&candidates[best]) {
+            best = i;
+        }
+    }
+    candidates.into_iter().nth(best).unwrap()
+}
+
+/// The tiles the ray from the center of `from` to the center of `to` passes,
+/// one per row like in [compute_fov](shadowcasting::compute_fov).
+/// A ray crossing a row exactly between two tiles is rounded up or down,
+/// shadowcasting sees `to` along one of the two if it is visible.
+fn center_ray(from: Pos, to: Pos, round_ties_up: bool) -> Vec<Pos> {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    let depth = dx.abs().max(dy.abs());
+    if depth == 0 {
+        return vec![from];
+    }
+    let (major, minor) = if dx.abs() >= dy.abs() { (dx, dy) } else { (dy, dx) };
+    (0..=depth)
+        .map(|row| {
+            // the column is row * minor / depth rounded, in halves to keep integers
+            let halves = 2 * row * minor;
+            let col = if round_ties_up {
+                (halves + depth).div_euclid(2 * depth)
+            } else {
+                -(-halves + depth).div_euclid(2 * depth)
+            };
+            let step = row * major.signum();
+            if dx.abs() >= dy.abs() {
+                Pos::new(from.x + step, from.y + col)
+            } else {
+                Pos::new(from.x + col, from.y + step)
+            }
+        })
+        .collect()
+}
+
+/// True if a projectile from `from` can hit `to`, anything on `to` itself doesn't block.
+pub fn can_hit(from: Pos, to: Pos, blocked: impl Fn(Pos) -> bool) -> bool {
+    let line = line_of_fire(from, to, &blocked);
+    first_blocker(&line, &blocked).is_none_or(|p| p == to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grids::Grid;
+
+    /// `#` blocks
+    fn grid(rows: &[&str]) -> Grid<bool> {
+        Grid::filled_with(rows[0].len() as i32, rows.len() as i32, |x, y| {
+            rows[y as usize].as_bytes()[x as usize] == b'#'
+        })
+    }
+
+    const ROOM: [&str; 7] = [
+        "#########",
+        "#.......#",
+        "#..#....#",
+        "#......##",
+        "#.#.....#",
+        "#....#..#",
+        "#########",
+    ];
+
+    #[test]
+    fn los_matches_fov_and_is_symmetric() {
+        let g = grid(&ROOM);
+        let blocks = |p: Pos| g.get_opt(p).is_none_or(|b| *b);
+        for from in g.coords().into_iter().filter(|p| !g[*p]) {
+            let mut fov = HashSet::new();
+            shadowcasting::compute_fov(from, &mut |p| blocks(p), &mut |p| {
+                fov.insert(p);
+            });
+            for to in g.coords() {
+                assert_eq!(fov.contains(&to), has_los(from, to, blocks), "{from:?} {to:?}");
+                if !g[to] {
+                    assert_eq!(has_los(from, to, blocks), has_los(to, from, blocks));
+                }
+            }
+            assert_eq!(fov, visible_within(from, 100, blocks));
+        }
+    }
+
+    #[test]
+    fn line_of_fire_test() {
+        let g = grid(&ROOM);
+        let blocks = |p: Pos| g.get_opt(p).is_none_or(|b| *b);
+        let (from, to) = (Pos::new(1, 1), Pos::new(7, 1));
+
+        let line = line_of_fire(from, to, blocks);
+        assert_eq!(Some(&from), line.first());
+        assert_eq!(Some(&to), line.last());
+        assert_eq!(None, first_blocker(&line, blocks));
+        assert!(can_hit(from, to, blocks));
+
+        // behind the pillar
+        let hidden = Pos::new(4, 3);
+        let line = line_of_fire(Pos::new(2, 1), hidden, blocks);
+        assert_eq!(Some(Pos::new(3, 2)), first_blocker(&line, blocks));
+        assert!(!can_hit(Pos::new(2, 1), hidden, blocks));
+
+        // something standing on the target doesn't stop the shot
+        let actor = |p: Pos| blocks(p) || p == to;
+        assert!(can_hit(from, to, actor));
+        assert_eq!(Some(to), first_blocker(&line_of_fire(from, to, actor), actor));
+    }
+
+    #[test]
+    fn can_hit_what_is_in_sight() {
+        let g = grid(&[
+            "###############",
+            "#.............#",
+            "#...#.....#...#",
+            "#......#......#",
+            "#..#.......#..#",
+            "#.............#",
+            "###############",
+        ]);
+        let blocks = |p: Pos| g.get_opt(p).is_none_or(|b| *b);
+        let floor: Vec<Pos> = g.coords().into_iter().filter(|p| !g[*p]).collect();
+        for &from in &floor {
+            for &to in &floor {
+                if has_los(from, to, blocks) {
+                    assert!(can_hit(from, to, blocks), "{from:?} {to:?}");
+                }
+            }
+        }
+    }
+}
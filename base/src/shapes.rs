@@ -0,0 +1,134 @@
+//! Templates for areas of effect.
+//! Distances are measured like [Pos::distance], so a disk is a square.
+//! The shapes don't know about any grid, positions outside of it have to be filtered out.
+
+use std::{collections::HashSet, f32::consts::FRAC_1_SQRT_2};
+
+use crate::{Pos, pos::IVec, shadowcasting};
+
+/// All tiles at most `radius` away, `center` included.
+pub fn disk(center: Pos, radius: i32) -> Vec<Pos> {
+    let mut r = Vec::new();
+    for y in (center.y - radius)..=(center.y + radius) {
+        for x in (center.x - radius)..=(center.x + radius) {
+            r.push(Pos::new(x, y));
+        }
+    }
+    r
+}
+
+/// All tiles exactly `radius` away.
+pub fn ring(center: Pos, radius: i32) -> Vec<Pos> {
+    if radius == 0 {
+        return vec![center];
+    }
+    center.circle_around(radius)
+}
+
+/// A 90° cone towards `dir` that is `length` tiles long, without `origin`.
+pub fn cone(origin: Pos, dir: IVec, length: i32) -> Vec<Pos> {
+    (1..=length)
+        .flat_map(|radius| origin.circle_around(radius))
+        .filter(|p| in_cone(dir, *p - origin))
+        .collect()
+}
+
+/// A straight line towards `dir` that is `length` tiles long, `origin` included.
+/// `dir` doesn't need to be normalized.
+pub fn line(origin: Pos, dir: IVec, length: i32) -> Vec<Pos> {
+    let steps = dir.x.abs().max(dir.y.abs()).max(1) as f32;
+    let scale = length as f32 / steps;
+    let end = origin
+        + IVec::new(
+            (dir.x as f32 * scale).round() as i32,
+            (dir.y as f32 * scale).round() as i32,
+        );
+    origin.bresenham(end)
+}
+
+/// Like [line] but stops before the first tile for which `blocked` is true.
+pub fn beam(origin: Pos, dir: IVec, length: i32, blocked: impl Fn(Pos) -> bool) -> Vec<Pos> {
+    line(origin, dir, length).into_iter().skip(1).take_while(|p| !blocked(*p)).collect()
+}
+
+/// Keeps the tiles of `shape` that can be seen from `center`.
+/// Blocking tiles that are seen are kept too, so an explosion can damage the walls it hits.
+pub fn clip(center: Pos, shape: Vec<Pos>, blocked: impl Fn(Pos) -> bool) -> Vec<Pos> {
+    let reach = shape.iter().map(|p| center.distance(*p)).max().unwrap_or(0);
+    let mut seen = HashSet::new();
+    shadowcasting::compute_fov(
+        center,
+        // nothing further away is needed, this keeps the scan small
+        &mut |p| center.distance(p) > reach || blocked(p),
+        &mut |p| {
+            seen.insert(p);
+        },
+    );
+    shape.into_iter().filter(|p| seen.contains(p)).collect()
+}
+
+/// A filled disk around `center` that doesn't go around corners.
+pub fn blast(center: Pos, radius: i32, blocked: impl Fn(Pos) -> bool) -> Vec<Pos> {
+    clip(center, disk(center, radius), blocked)
+}
+
+/// True if `v` is at most 45° away from `dir`.
+fn in_cone(dir: IVec, v: IVec) -> bool {
+    let dot = (dir.x * v.x + dir.y * v.y) as f32;
+    let dir_len = ((dir.x * dir.x + dir.y * dir.y) as f32).sqrt();
+    let v_len = ((v.x * v.x + v.y * v.y) as f32).sqrt();
+    if dir_len == 0. || v_len == 0. {
+        return false;
+    }
+    // small epsilon so the diagonal edges of the cone are included
+    dot / (dir_len * v_len) >= FRAC_1_SQRT_2 - 0.01
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let c = Pos::new(5, 5);
+        assert_eq!(25, disk(c, 2).len());
+        assert_eq!(vec![c], disk(c, 0));
+        assert_eq!(16, ring(c, 2).len());
+        assert!(ring(c, 2).iter().all(|p| c.distance(*p) == 2));
+        assert_eq!(vec![c], ring(c, 0));
+
+        let right = IVec::new(1, 0);
+        let cone = cone(c, right, 2);
+        assert!(cone.contains(&Pos::new(6, 5)));
+        assert!(cone.contains(&Pos::new(6, 4)));
+        assert!(cone.contains(&Pos::new(7, 3)));
+        assert!(!cone.contains(&Pos::new(7, 2)));
+        assert!(!cone.contains(&c));
+        assert!(cone.iter().all(|p| p.x > c.x));
+
+        let line = line(c, IVec::new(2, 2), 3);
+        assert_eq!(vec![c, Pos::new(6, 6), Pos::new(7, 7), Pos::new(8, 8)], line);
+    }
+
+    #[test]
+    fn blocked_shapes() {
+        let c = Pos::new(5, 5);
+        let wall = |p: Pos| p.x == 7;
+
+        let beam = beam(c, IVec::new(1, 0), 5, wall);
+        assert_eq!(vec![Pos::new(6, 5)], beam);
+
+        let blast = blast(c, 3, wall);
+        assert!(blast.contains(&c));
+        assert!(blast.contains(&Pos::new(7, 5)), "the wall itself is hit");
+        assert!(!blast.contains(&Pos::new(8, 5)), "behind the wall");
+        assert!(blast.contains(&Pos::new(3, 3)));
+
+        // a pillar casts a shadow
+        let pillar = |p: Pos| p == Pos::new(6, 5);
+        let clipped = clip(c, ring(c, 3), pillar);
+        assert!(!clipped.contains(&Pos::new(8, 5)));
+        assert!(clipped.contains(&Pos::new(2, 5)));
+        assert_eq!(ring(c, 3).len(), clip(c, ring(c, 3), |_| false).len());
+    }
+}
@@ -276,6 +276,9 @@ pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a + t * (b - a)
 }
 
+pub const BUMP_ATTACK_LENGTH: f32 = 0.15;
+pub const MOVE_LENGTH: f32 = 0.10;
+
 pub fn spawn_bump_attack_animation(
     world: &World,
     e: Entity,
@@ -285,25 +288,32 @@ pub fn spawn_bump_attack_animation(
     hp_bar_animation: HPBarAnimation,
 ) -> Entity {
     zone!();
-    let start = pos_to_drawpos(start_p);
-    let end = pos_to_drawpos(end_p);
-    let animation_length = 0.15;
-
-    let current_time = world.singleton::<GameTime>().0;
-
-    let start_time = [e, target]
-        .into_iter()
-        .flat_map(|e| query!(world, AnimationTimer, AnimationTarget(this, *e)))
-        .map(|(timer,)| timer.end)
-        .fold(current_time, f32::max);
+    let start_time = start_time(world, &[e, target]);
+    spawn_bump_attack_animation_at(
+        world,
+        e,
+        target,
+        start_p,
+        end_p,
+        hp_bar_animation,
+        start_time,
+    )
+}
 
-    // bump animation
-    let animation_e = world
-        .create_deferred()
-        .add(AnimationTimer { start: start_time, end: start_time + animation_length })
-        .add(BumpAttackAnimation { start, end })
-        .relate_to::<AnimationTarget>(e)
-        .entity;
+/// Like [spawn_bump_attack_animation] but starting at the given time.
+/// Used when the animations before it are not spawned yet.
+pub fn spawn_bump_attack_animation_at(
+    world: &World,
+    e: Entity,
+    target: Entity,
+    start_p: Pos,
+    end_p: Pos,
+    hp_bar_animation: HPBarAnimation,
+    start_time: f32,
+) -> Entity {
+    zone!();
+    let animation_length = BUMP_ATTACK_LENGTH;
+    let animation_e = spawn_bump_animation_at(world, e, start_p, end_p, start_time);
 
     // hp bar animation
     world
@@ -331,16 +341,43 @@ pub fn spawn_bump_attack_animation(
     return animation_e;
 }
 
-pub fn spawn_move_animation(world: &World, e: Entity, start: Pos, end: Pos) -> Entity {
+/// Just the movement of a bump attack, for hitting things that are not actors.
+pub fn spawn_bump_animation_at(
+    world: &World,
+    e: Entity,
+    start_p: Pos,
+    end_p: Pos,
+    start_time: f32,
+) -> Entity {
     zone!();
-    assert!(world.has_component::<DrawPos>(e));
+    let start = pos_to_drawpos(start_p);
+    let end = pos_to_drawpos(end_p);
+    world
+        .create_deferred()
+        .add(AnimationTimer { start: start_time, end: start_time + BUMP_ATTACK_LENGTH })
+        .add(BumpAttackAnimation { start, end })
+        .relate_to::<AnimationTarget>(e)
+        .entity
+}
 
-    let animation_length = 0.10;
-    let current_time = world.singleton::<GameTime>().0;
+pub fn spawn_move_animation(world: &World, e: Entity, start: Pos, end: Pos) -> Entity {
+    zone!();
+    let start_time = start_time(world, &[e]);
+    spawn_move_animation_at(world, e, start, end, start_time, MOVE_LENGTH)
+}
 
-    let start_time = query!(world, AnimationTimer, AnimationTarget(this, *e))
-        .map(|(timer,)| timer.end)
-        .fold(current_time, f32::max);
+/// Like [spawn_move_animation] but starting at the given time with the given length.
+/// Used when the animations before it are not spawned yet.
+pub fn spawn_move_animation_at(
+    world: &World,
+    e: Entity,
+    start: Pos,
+    end: Pos,
+    start_time: f32,
+    animation_length: f32,
+) -> Entity {
+    zone!();
+    assert!(world.has_component::<DrawPos>(e));
 
     world
         .create_deferred()
@@ -441,6 +478,23 @@ pub fn start_time(world: &World, targets: &[Entity]) -> f32 {
     start_time
 }
 
+/// Plays the hp bar change of target at the given time.
+pub fn spawn_hp_bar_animation_at(
+    world: &World,
+    target: Entity,
+    hp_bar_animation: HPBarAnimation,
+    start_time: f32,
+    animation_length: f32,
+) -> Entity {
+    zone!();
+    world
+        .create_deferred()
+        .add(AnimationTimer::new(start_time, animation_length))
+        .add(hp_bar_animation)
+        .relate_to::<AnimationTarget>(target)
+        .entity
+}
+
 // TODO use this as base for other animation spawn functions
 pub fn spawn_empty_animation(
     world: &World,
@@ -449,7 +503,16 @@ pub fn spawn_empty_animation(
 ) -> EntityViewDeferred<'_> {
     zone!();
     let start_time = start_time(world, &[target]);
+    spawn_empty_animation_at(world, target, start_time, animation_length)
+}
 
+pub fn spawn_empty_animation_at(
+    world: &World,
+    target: Entity,
+    start_time: f32,
+    animation_length: f32,
+) -> EntityViewDeferred<'_> {
+    zone!();
     let anim = world.create_deferred();
     anim.add(AnimationTimer { start: start_time, end: start_time + animation_length })
         .relate_to::<AnimationTarget>(target);
@@ -0,0 +1,101 @@
+//! Generates levels without starting the game.
+//!
+//! ```text
+//! mapgen <seed> [depth] [--png <file>]
+//! mapgen --batch <from>..<to> [depth] [--out <dir>]
+//! ```
+//!
+//! The first form prints the level as text and can also write it as PNG.
+//! The batch form prints a summary line for every seed in the range,
+//! with `--out` the text and PNG of every level are written into the directory.
+
+use std::path::{Path, PathBuf};
+
+use worker::mapgen_export::inspect;
+
+const USAGE: &str = "usage:
+  mapgen <seed> [depth] [--png <file>]
+  mapgen --batch <from>..<to> [depth] [--out <dir>]";
+
+fn main() {
+    if let Err(e) = run(std::env::args().skip(1).collect()) {
+        eprintln!("{e}\n{USAGE}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut batch = None;
+    let mut png = None;
+    let mut out = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--batch" => batch = Some(value()?),
+            "--png" => png = Some(PathBuf::from(value()?)),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let number = |s: &str| s.parse::<u64>().map_err(|e| format!("'{s}' is no number: {e}"));
+
+    match batch {
+        None => {
+            let [seed, rest @ ..] = positional.as_slice() else {
+                return Err("missing seed".into());
+            };
+            let depth = parse_depth(rest)?;
+            let inspection = inspect(number(seed)?, depth);
+            println!("{}", inspection.summary());
+            for (seed, problem) in &inspection.trace.rejected {
+                println!("rejected attempt with seed {seed}: {problem:?}");
+            }
+            print!("{}", inspection.ascii());
+            if let Some(path) = png {
+                write(&path, &inspection.png())?;
+            }
+        }
+        Some(range) => {
+            let (from, to) = range
+                .split_once("..")
+                .ok_or_else(|| format!("'{range}' is no range like 0..100"))?;
+            let depth = parse_depth(&positional)?;
+            if let Some(dir) = &out {
+                std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+            }
+            let mut broken = 0;
+            let mut fallbacks = 0;
+            for seed in number(from)?..number(to)? {
+                let inspection = inspect(seed, depth);
+                println!("{}", inspection.summary());
+                broken += inspection.problem.is_some() as usize;
+                fallbacks += (inspection.trace.generator == "fallback") as usize;
+                if let Some(dir) = &out {
+                    let name = format!("seed-{seed}-depth-{depth}");
+                    write(&dir.join(format!("{name}.txt")), inspection.ascii().as_bytes())?;
+                    write(&dir.join(format!("{name}.png")), &inspection.png())?;
+                }
+            }
+            println!("{broken} broken levels, {fallbacks} fell back to a plain room");
+        }
+    }
+    Ok(())
+}
+
+fn parse_depth(rest: &[String]) -> Result<i32, String> {
+    match rest {
+        [] => Ok(1),
+        [depth] => depth.parse().map_err(|e| format!("'{depth}' is no depth: {e}")),
+        _ => Err(format!("unexpected arguments {rest:?}")),
+    }
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
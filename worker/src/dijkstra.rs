@@ -1,74 +1,162 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
 use base::{Pos, grids::Grid, zone};
 
-/// spreads out values, starting from the seeds
-/// the seed positions are supposed to be set to a > 0 value before using this function
-pub fn dijkstra<F: Fn(Pos) -> i32>(grid: &mut Grid<i32>, seed: &[Pos], cost: F) {
+const DIRECTIONS: [(i32, i32); 8] =
+    [(-1, 0), (1, 0), (0, -1), (1, -1), (-1, -1), (0, 1), (1, 1), (-1, 1)];
+
+/// Cost for tiles that can't be entered at all
+pub const IMPASSABLE: i32 = i32::MAX;
+
+/// Distance to the closest goal for every tile, walking in 8 directions.
+/// `cost` is paid for entering a tile, goals start out with their weight instead of 0,
+/// which makes some goals less attractive than others.
+/// Tiles that can't reach any goal are [IMPASSABLE].
+pub fn dijkstra_map<F: Fn(Pos) -> i32>(
+    width: i32,
+    height: i32,
+    goals: &[(Pos, i32)],
+    cost: F,
+) -> Grid<i32> {
     zone!();
-    let mut next: Vec<Pos> = seed.iter().flat_map(|pos| pos.neighbors(grid)).collect();
-    next.extend(seed.iter()); // sometimes its necessary to recompute seeds too
-
-    while !next.is_empty() {
-        let buffer: Vec<_> = next.drain(..).collect();
-        for pos in buffer.into_iter() {
-            let neighbor_max = pos
-                .neighbors(grid)
-                .into_iter()
-                .map(|pos| grid.get_clamped(pos.x, pos.y))
-                .max();
-            if let Some(neighbor_max) = neighbor_max {
-                let v = *grid.get_clamped_v(pos);
-                let c = cost(pos);
-                if *neighbor_max > v + c {
-                    let new_val = neighbor_max - c;
-                    *grid.get_mut(pos.x, pos.y) = new_val;
-                    next.extend(
-                        pos.neighbors(grid)
-                            .into_iter()
-                            .filter(|pos| *grid.get(pos.x, pos.y) < new_val - cost(*pos)),
-                    );
-                }
+    let mut distances = Grid::new(width, height, IMPASSABLE);
+    let mut todo = BinaryHeap::new();
+    for &(pos, weight) in goals {
+        if distances.get_opt(pos).is_some_and(|d| weight < *d) {
+            distances[pos] = weight;
+            todo.push(Reverse((weight, pos)));
+        }
+    }
+    spread(&mut distances, todo, cost);
+    distances
+}
+
+/// Updates a map from [dijkstra_map] after the cost of the `changed` tiles went down,
+/// only the tiles that get closer to a goal are touched.
+/// Costs that went up are not handled, the map has to be built again for those.
+pub fn dijkstra_lower_costs<F: Fn(Pos) -> i32>(
+    distances: &mut Grid<i32>,
+    changed: &[Pos],
+    cost: F,
+) {
+    zone!();
+    let mut todo = BinaryHeap::new();
+    for &pos in changed {
+        let cost = cost(pos);
+        if cost == IMPASSABLE {
+            continue;
+        }
+        let closest = pos.neighbors(distances).map(|next| distances[next]).min();
+        let Some(closest) = closest.filter(|d| *d != IMPASSABLE) else { continue };
+        let new_distance = closest.saturating_add(cost);
+        if new_distance < distances[pos] {
+            distances[pos] = new_distance;
+            todo.push(Reverse((new_distance, pos)));
+        }
+    }
+    spread(distances, todo, cost);
+}
+
+fn spread<F: Fn(Pos) -> i32>(
+    distances: &mut Grid<i32>,
+    mut todo: BinaryHeap<Reverse<(i32, Pos)>>,
+    cost: F,
+) {
+    while let Some(Reverse((distance, pos))) = todo.pop() {
+        if distance > distances[pos] {
+            continue; // already found a shorter way here
+        }
+        for dir in DIRECTIONS {
+            let next = pos + dir;
+            let Some(&known) = distances.get_opt(next) else { continue };
+            let cost = cost(next);
+            if cost == IMPASSABLE {
+                continue;
+            }
+            let new_distance = distance.saturating_add(cost);
+            if new_distance < known {
+                distances[next] = new_distance;
+                todo.push(Reverse((new_distance, next)));
             }
         }
     }
 }
 
-/// returns path that follows increasing values until it reaches a local maximium
-pub fn dijkstra_path(grid: &Grid<i32>, start: Pos) -> Vec<Pos> {
+/// Returns the path that follows decreasing distances until it reaches a goal.
+/// Empty if `start` can't reach a goal.
+pub fn dijkstra_path(distances: &Grid<i32>, start: Pos) -> Vec<Pos> {
     zone!();
     let mut path = Vec::new();
-    if start.x < 0 || start.y <= 0 || start.x >= grid.width || start.y >= grid.height {
-        return path;
-    }
-    let mut pos = start;
-    let mut v = grid[pos];
-    if v <= 0 {
+    let Some(&start_distance) = distances.get_opt(start) else { return path };
+    if start_distance == IMPASSABLE {
         return path;
     }
     path.push(start);
-    // do while at home
-    while {
-        // neighbor with maximum value
-        let (npos, nv) = pos
-            .neighbors(grid)
-            .into_iter()
-            .map(|npos| (npos, grid[npos]))
-            .max_by_key(|(_, v)| *v)
-            .unwrap();
-        if nv > v {
-            path.push(npos);
-            pos = npos;
-            v = nv;
-            true
-        } else {
-            false
+    let (mut pos, mut distance) = (start, start_distance);
+    loop {
+        let closest = pos
+            .neighbors(distances)
+            .map(|next| (next, distances[next]))
+            .min_by_key(|(_, d)| *d);
+        match closest {
+            Some((next, d)) if d < distance => {
+                path.push(next);
+                pos = next;
+                distance = d;
+            }
+            _ => break,
         }
-    } {}
+    }
     path
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use super::*;
+    use crate::rand::RandomGenerator;
+
+    /// The iterative relaxation this module used before, kept as reference.
+    /// Works on "bigger is closer" values, the seeds have to be set before.
+    fn relaxation_dijkstra<F: Fn(Pos) -> i32>(grid: &mut Grid<i32>, seed: &[Pos], cost: F) {
+        let mut next: Vec<Pos> = seed.iter().flat_map(|pos| pos.neighbors(grid)).collect();
+        next.extend(seed.iter());
+
+        while !next.is_empty() {
+            let buffer: Vec<_> = next.drain(..).collect();
+            for pos in buffer.into_iter() {
+                let neighbor_max =
+                    pos.neighbors(grid).map(|pos| grid.get_clamped(pos.x, pos.y)).max();
+                if let Some(neighbor_max) = neighbor_max {
+                    let v = *grid.get_clamped_v(pos);
+                    let c = cost(pos);
+                    if *neighbor_max > v + c {
+                        let new_val = neighbor_max - c;
+                        *grid.get_mut(pos.x, pos.y) = new_val;
+                        next.extend(
+                            pos.neighbors(grid)
+                                .filter(|pos| *grid.get(pos.x, pos.y) < new_val - cost(*pos)),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Costs between 1 and 3 with some impassable tiles sprinkled in.
+    fn random_costs(rand: &mut RandomGenerator, width: i32, height: i32) -> Grid<i32> {
+        let mut costs = Grid::new(width, height, 1);
+        for pos in costs.coords() {
+            costs[pos] = match rand.next_in_range(0, 10) {
+                0..2 => IMPASSABLE,
+                2..4 => 3,
+                4..6 => 2,
+                _ => 1,
+            };
+        }
+        costs
+    }
 
     #[test]
     fn get_neighbors_test() {
@@ -90,28 +178,135 @@ mod tests {
     #[test]
     fn dijkstra_map_test() {
         // basic
-        let mut grid = Grid::new(10, 10, 0);
         let pos = Pos::new(5, 5);
-        *grid.get_clamped_mut(pos.x, pos.y) = 5;
-        dijkstra(&mut grid, &[pos], |_| 1);
-        assert_eq!(2, *grid.get(2, 5));
+        let grid = dijkstra_map(10, 10, &[(pos, 0)], |_| 1);
+        assert_eq!(3, grid[Pos::new(2, 5)]);
 
         // higher cost
-        let mut grid = Grid::new(10, 10, 0);
-        let pos = Pos::new(5, 5);
-        *grid.get_clamped_mut(pos.x, pos.y) = 5;
-        dijkstra(&mut grid, &[pos], |_| 2);
-        assert_eq!(0, *grid.get(2, 5));
-        assert_eq!(1, *grid.get(3, 5));
+        let grid = dijkstra_map(10, 10, &[(pos, 0)], |_| 2);
+        assert_eq!(6, grid[Pos::new(2, 5)]);
+        assert_eq!(4, grid[Pos::new(3, 5)]);
 
-        // multiple seeds
-        let mut grid = Grid::new(10, 10, 0);
-        let pos = Pos::new(5, 5);
-        *grid.get_clamped_mut(pos.x, pos.y) = 5;
+        // multiple goals
         let pos2 = Pos::new(1, 4);
-        *grid.get_clamped_mut(pos2.x, pos2.y) = 5;
-        dijkstra(&mut grid, &[pos, pos2], |_| 1);
-        assert_eq!(4, *grid.get(2, 5));
-        assert_eq!(3, *grid.get(3, 5));
+        let grid = dijkstra_map(10, 10, &[(pos, 0), (pos2, 0)], |_| 1);
+        assert_eq!(1, grid[Pos::new(2, 5)]);
+        assert_eq!(2, grid[Pos::new(3, 5)]);
+
+        // weighted goals
+        let grid = dijkstra_map(10, 10, &[(pos, 0), (pos2, 3)], |_| 1);
+        assert_eq!(3, grid[pos2]);
+        assert_eq!(3, grid[Pos::new(2, 5)]);
+    }
+
+    #[test]
+    fn same_as_relaxation() {
+        let rand = &mut RandomGenerator::new(42);
+        for _ in 0..50 {
+            let (width, height) =
+                (rand.next_in_range(3, 20) as i32, rand.next_in_range(3, 20) as i32);
+            let mut costs = random_costs(rand, width, height);
+            let goals: Vec<(Pos, i32)> = (0..rand.next_in_range(1, 4))
+                .map(|_| {
+                    let pos = rand.pick_random(&costs.coords());
+                    (pos, rand.next_in_range(0, 5) as i32)
+                })
+                .collect();
+            // the old version overflows on impassable seeds
+            for (pos, _) in &goals {
+                costs[*pos] = 1;
+            }
+
+            let mut expected = Grid::new(width, height, 0);
+            for (pos, weight) in &goals {
+                expected[*pos] = expected[*pos].max(500 - weight);
+            }
+            let seeds: Vec<Pos> = goals.iter().map(|(pos, _)| *pos).collect();
+            relaxation_dijkstra(&mut expected, &seeds, |pos| costs[pos]);
+
+            let distances = dijkstra_map(width, height, &goals, |pos| costs[pos]);
+            for pos in costs.coords() {
+                let converted = if distances[pos] < 500 { 500 - distances[pos] } else { 0 };
+                assert_eq!(expected[pos], converted, "{pos:?} with goals {goals:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn long_distances() {
+        // the old version stopped spreading after 500
+        let grid = dijkstra_map(700, 1, &[(Pos::new(0, 0), 0)], |_| 1);
+        assert_eq!(699, grid[Pos::new(699, 0)]);
+        assert_eq!(700, dijkstra_path(&grid, Pos::new(699, 0)).len());
+    }
+
+    #[test]
+    fn path_test() {
+        let goal = Pos::new(5, 0);
+        let walls = [Pos::new(4, 0), Pos::new(4, 1), Pos::new(4, 2)];
+        let cost = |pos| if walls.contains(&pos) { IMPASSABLE } else { 1 };
+        let grid = dijkstra_map(10, 10, &[(goal, 0)], cost);
+        assert_eq!(IMPASSABLE, grid[walls[0]]);
+
+        // paths along the top row used to be empty
+        let path = dijkstra_path(&grid, Pos::new(0, 0));
+        assert_eq!(Some(&Pos::new(0, 0)), path.first());
+        assert_eq!(Some(&goal), path.last());
+        assert!(path.iter().all(|pos| !walls.contains(pos)));
+        assert_eq!(grid[Pos::new(0, 0)] as usize + 1, path.len());
+
+        let blocked = dijkstra_map(10, 10, &[(goal, 0)], |_| IMPASSABLE);
+        assert!(dijkstra_path(&blocked, Pos::new(0, 0)).is_empty());
+        assert_eq!(vec![goal], dijkstra_path(&blocked, goal));
+    }
+
+    #[test]
+    fn lower_costs_test() {
+        let rand = &mut RandomGenerator::new(3);
+        for _ in 0..50 {
+            let (width, height) = (20, 15);
+            let mut costs = random_costs(rand, width, height);
+            let goals = [(rand.pick_random(&costs.coords()), 0)];
+            let mut distances = dijkstra_map(width, height, &goals, |pos| costs[pos]);
+
+            // walls get knocked down, doors opened
+            let mut changed = Vec::new();
+            for _ in 0..rand.next_in_range(1, 6) {
+                let pos = rand.pick_random(&costs.coords());
+                costs[pos] = if costs[pos] == IMPASSABLE { 2 } else { 1 };
+                changed.push(pos);
+            }
+            dijkstra_lower_costs(&mut distances, &changed, |pos| costs[pos]);
+
+            let expected = dijkstra_map(width, height, &goals, |pos| costs[pos]);
+            assert_eq!(expected.data, distances.data, "changed {changed:?}");
+        }
+    }
+
+    /// `cargo test --release -- --ignored --nocapture bench_dijkstra`
+    #[test]
+    #[ignore]
+    fn bench_dijkstra() {
+        let rand = &mut RandomGenerator::new(7);
+        for size in [40, 80, 160] {
+            let costs = random_costs(rand, size, size);
+            let goal = Pos::new(size / 2, size / 2);
+            let runs = 20;
+
+            let start = Instant::now();
+            for _ in 0..runs {
+                let mut grid = Grid::new(size, size, 0);
+                grid[goal] = 500;
+                relaxation_dijkstra(&mut grid, &[goal], |pos| costs[pos]);
+            }
+            let relaxation = start.elapsed() / runs;
+
+            let start = Instant::now();
+            for _ in 0..runs {
+                dijkstra_map(size, size, &[(goal, 0)], |pos| costs[pos]);
+            }
+            let heap = start.elapsed() / runs;
+            println!("{size}x{size}: relaxation {relaxation:?}, binary heap {heap:?}");
+        }
     }
 }
@@ -1,10 +1,17 @@
+pub mod attacks;
+pub mod autotile;
 pub mod debug_util;
+pub mod destruction;
 pub mod drawing;
+pub mod dungeon;
 pub mod ecs_types;
 pub mod game_ai;
 pub mod game_logic;
 pub mod input_handling;
+pub mod knockback;
+pub mod lighting;
 pub mod mapgen;
+pub mod spatial;
 pub mod sprites;
 pub mod tile_map;
 pub mod ui;
@@ -19,19 +26,20 @@ use crate::{animation, game::drawing::DrawHealth, persistent::PersistentState};
 use animation::AnimationTarget;
 use base::text::Labelize;
 use base::zone;
-use base::{Color, ContextTrait, FPos, Input, Pos, Rect, shadowcasting};
+use base::{Color, ContextTrait, FPos, Input, Pos, Rect};
 use base::{FVec, TextProperty};
 use debug_util::{DebugOptions, debug_ui};
 use drawing::draw_systems;
 use ecs_types::*;
 use froql::entity_store::Entity;
 use froql::{query, world::World};
-use game_ai::{Pathfinding, ai_turn};
+use game_ai::{PathCache, ai_turn, remember_target};
 use game_logic::{
-    Actor, DelayedAction, Fov, Player, TileEffect, create_world, handle_action, handle_death,
-    handle_delayed_action, next_turn_actor, player_is_alive, raise_pulse,
+    Actor, DelayedAction, Fov, Player, TileEffect, create_world, damage_taken, handle_action,
+    handle_death, handle_delayed_action, next_turn_actor, player_is_alive, raise_pulse,
 };
 use input_handling::{avy_navigation, input_direction, player_inputs};
+use lighting::{LightMap, refresh_lighting, update_lighting};
 use quicksilver::Quicksilver;
 use sprites::{TILE_SIZE, pos_to_drawpos};
 use tile_map::TileMap;
@@ -67,7 +75,7 @@ pub struct AbilityUIState {
     ability_selected: PlayerAbility,
 }
 
-#[derive(Default, Quicksilver, Clone, Copy)]
+#[derive(Debug, Default, Quicksilver, Clone, Copy, PartialEq)]
 #[repr(C)]
 pub enum PlayerAbility {
     #[default]
@@ -78,6 +86,49 @@ pub enum PlayerAbility {
     JumpAttack,
 }
 
+impl PlayerAbility {
+    /// In the order of the ability hotkeys
+    pub const HOTKEYS: [PlayerAbility; 5] = [
+        PlayerAbility::ThrowRock,
+        PlayerAbility::Kick,
+        PlayerAbility::GroundSlam,
+        PlayerAbility::JumpAttack,
+        PlayerAbility::Meditate,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PlayerAbility::ThrowRock => "Throw Rock",
+            PlayerAbility::Kick => "Kick",
+            PlayerAbility::Meditate => "Meditate",
+            PlayerAbility::GroundSlam => "Ground Slam",
+            PlayerAbility::JumpAttack => "Jump Attack",
+        }
+    }
+
+    /// aut until the ability can be used again
+    pub fn cooldown(self) -> i64 {
+        match self {
+            PlayerAbility::ThrowRock => 0,
+            PlayerAbility::Kick => 20,
+            PlayerAbility::Meditate => 100,
+            PlayerAbility::GroundSlam => 50,
+            PlayerAbility::JumpAttack => 30,
+        }
+    }
+
+    /// rage spent when using the ability
+    pub fn rage_cost(self) -> i32 {
+        match self {
+            PlayerAbility::ThrowRock => 0,
+            PlayerAbility::Kick => 3,
+            PlayerAbility::Meditate => 0,
+            PlayerAbility::GroundSlam => 6,
+            PlayerAbility::JumpAttack => 4,
+        }
+    }
+}
+
 #[derive(Default, Quicksilver)]
 pub struct InspectUIState {
     cursor_pos: Option<Pos>,
@@ -104,6 +155,7 @@ pub fn update_inner(c: &mut dyn ContextTrait, s: &mut PersistentState) {
 
     ensure_singleton::<GameTime>(world);
     ensure_singleton::<DebugOptions>(world);
+    ensure_singleton::<LightMap>(world);
 
     let state: UIState = world.singleton::<UI>().state;
 
@@ -259,7 +311,7 @@ fn update_systems_inspect(c: &mut dyn ContextTrait, world: &mut World) {
 
         for (actor, _draw_pos) in query!(world, Actor, DrawPos) {
             // TODO check if draw_pos is visible
-            if fov.0.contains(&actor.pos) {
+            if fov.sees_actor_at(actor.pos) {
                 positions.push(actor.pos);
             }
         }
@@ -273,8 +325,11 @@ fn update_systems_inspect(c: &mut dyn ContextTrait, world: &mut World) {
 fn update_systems_normal(c: &mut dyn ContextTrait, world: &mut World) {
     zone!();
 
-    handle_turn(c, world);
+    let turn_taken = handle_turn(c, world);
     world.process();
+    if turn_taken {
+        update_lighting(world);
+    }
 
     for (actor, mut draw_pos, mut draw_health) in
         query!(world, Actor, mut DrawPos, mut DrawHealth, !AnimationTarget(_, this))
@@ -286,14 +341,18 @@ fn update_systems_normal(c: &mut dyn ContextTrait, world: &mut World) {
     world.process();
 
     // update player fov
-    for (tm, actor, mut fov) in query!(world, $ TileMap, _ Player, Actor, mut Fov) {
-        shadowcasting::compute_fov(actor.pos, &mut |pos| tm.blocks_vision(pos), &mut |pos| {
-            fov.0.insert(pos);
-        });
+    refresh_lighting(world);
+    for (tm, light, actor, mut fov) in
+        query!(world, $ TileMap, $ LightMap, _ Player, Actor, mut Fov)
+    {
+        let monsters = query!(world, &this, Actor, !Player)
+            .map(|(e, a)| (e.entity, a.pos, a.sprite, a.creature_type.stealth()));
+        fov.update(actor.pos, &tm, &light, monsters);
     }
 }
 
-fn handle_turn(c: &mut dyn ContextTrait, world: &mut World) {
+/// Returns true if the player took a turn.
+fn handle_turn(c: &mut dyn ContextTrait, world: &mut World) -> bool {
     zone!();
 
     if !player_is_animation_target(world) {
@@ -307,37 +366,38 @@ fn handle_turn(c: &mut dyn ContextTrait, world: &mut World) {
         // handle player input
         TileMap::update_caches(world);
         let Some(action) = player_inputs(c, world) else {
-            return;
+            return false;
         };
         handle_action(world, action);
         world.process();
         TileMap::update_caches(world);
 
         // handle AI input after player
-        let Some(mut current) = next_turn_actor(world) else { return };
-        let pf = Pathfinding::new(world);
+        let Some(mut current) = next_turn_actor(world) else { return true };
+        ensure_singleton::<PathCache>(world);
 
         while !world.has_component::<Player>(current) && player_is_alive(world) {
-            TileMap::update_caches(world);
             on_turn_start(world, current);
 
-            if let Some(DelayedAction { action, .. }) = world.take_component(current) {
-                handle_delayed_action(world, action);
+            if let Some(delayed) = world.take_component::<DelayedAction>(current) {
+                handle_delayed_action(world, current, delayed);
             } else {
-                let action = ai_turn(world, &pf, current);
+                let action = ai_turn(world, current);
                 handle_action(world, action);
             }
+            remember_target(world, current);
 
-            let Some(next) = next_turn_actor(world) else { return };
+            let Some(next) = next_turn_actor(world) else { return true };
             current = next;
         }
 
         // turn start effect for the player
         if world.has_component::<Player>(current) && player_is_alive(world) {
-            TileMap::update_caches(world);
             on_turn_start(world, current);
         }
+        return true;
     }
+    false
 }
 
 pub fn on_turn_start(world: &mut World, e: Entity) {
@@ -347,12 +407,12 @@ pub fn on_turn_start(world: &mut World, e: Entity) {
         let effect = world.get_component::<TileEffect>(effect);
         match *effect {
             TileEffect::Burning => {
-                let dmg = 2;
+                let dmg = damage_taken(world, e, 2);
                 let hp_change = actor.hp.dmg(dmg);
                 let a = animation::spawn_empty_animation(world, e, 0.25).add(hp_change).entity;
                 log_message(world, format!("{} burns for {dmg} damage.", actor.name), a);
                 raise_pulse(world, e, &mut actor);
-                handle_death(world, e, &actor, a);
+                handle_death(world, None, e, &actor, a);
             }
         }
     };
@@ -0,0 +1,55 @@
+use base::{Pos, pos::IVec, shapes, zone};
+use quicksilver::Quicksilver;
+
+use crate::game::tile_map::TileMap;
+
+/// The area a telegraphed attack hits.
+/// Walls stop the attack, so the footprint only contains tiles the attacker can see.
+#[derive(Debug, Quicksilver, Clone, Copy)]
+pub enum AttackShape {
+    /// The tile next to the attacker in that direction
+    Single { dir: IVec },
+    /// A line towards `dir` that is `length` tiles long
+    Line { dir: IVec, length: i32 },
+    /// A 90° cone towards `dir`
+    Cone { dir: IVec, length: i32 },
+    /// All tiles at exactly `radius` distance
+    Ring { radius: i32 },
+    /// All 8 tiles around the attacker
+    Around,
+}
+
+impl AttackShape {
+    /// Name of the attack, used in log messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AttackShape::Single { .. } => "smash",
+            AttackShape::Line { .. } => "blast",
+            AttackShape::Cone { .. } => "cleave",
+            AttackShape::Ring { .. } => "shockwave",
+            AttackShape::Around => "sweep",
+        }
+    }
+
+    /// The tiles hit when the attack comes from `origin`.
+    /// Never contains `origin` itself.
+    pub fn footprint(&self, origin: Pos, tm: &TileMap) -> Vec<Pos> {
+        zone!();
+        let blocked = |p: Pos| tm.blocks_vision(p);
+        let reached = match *self {
+            AttackShape::Single { dir } => shapes::clip(origin, vec![origin + dir], blocked),
+            AttackShape::Line { dir, length } => shapes::beam(origin, dir, length, blocked),
+            AttackShape::Cone { dir, length } => {
+                shapes::clip(origin, shapes::cone(origin, dir, length), blocked)
+            }
+            AttackShape::Ring { radius } => {
+                shapes::clip(origin, shapes::ring(origin, radius), blocked)
+            }
+            AttackShape::Around => shapes::clip(origin, shapes::ring(origin, 1), blocked),
+        };
+        reached
+            .into_iter()
+            .filter(|p| *p != origin && tm.tiles.is_valid(*p) && !tm.blocks_vision(*p))
+            .collect()
+    }
+}
@@ -0,0 +1,261 @@
+//! Picks sprites for tiles by looking at their neighbours.
+//!
+//! Every tile gets an 8 bit mask of which neighbours belong to the same group,
+//! a family of sprites then maps that mask to the fitting sprite.
+//! Families with several equivalent sprites pick one per position,
+//! so the map looks varied but doesn't flicker.
+
+use base::{Pos, grids::Grid};
+
+use crate::game::sprites::LogicTile;
+
+pub const N: u8 = 1 << 0;
+pub const NE: u8 = 1 << 1;
+pub const E: u8 = 1 << 2;
+pub const SE: u8 = 1 << 3;
+pub const S: u8 = 1 << 4;
+pub const SW: u8 = 1 << 5;
+pub const W: u8 = 1 << 6;
+pub const NW: u8 = 1 << 7;
+/// All neighbours are in the group
+pub const ALL: u8 = 0xff;
+
+const DIRECTIONS: [(u8, (i32, i32)); 8] = [
+    (N, (0, -1)),
+    (NE, (1, -1)),
+    (E, (1, 0)),
+    (SE, (1, 1)),
+    (S, (0, 1)),
+    (SW, (-1, 1)),
+    (W, (-1, 0)),
+    (NW, (-1, -1)),
+];
+
+/// Sets the bit of every neighbour for which `same` is true.
+/// Neighbours outside the map count as `outside`.
+pub fn neighbor_mask(
+    tiles: &Grid<LogicTile>,
+    pos: Pos,
+    outside: bool,
+    same: impl Fn(LogicTile) -> bool,
+) -> u8 {
+    let mut mask = 0;
+    for (bit, offset) in DIRECTIONS {
+        let is_same = tiles.get_opt(pos + offset).map_or(outside, |tile| same(*tile));
+        if is_same {
+            mask |= bit;
+        }
+    }
+    mask
+}
+
+/// The parts of the border of a tile that touch a tile outside of its group.
+/// These are the open sides and the diagonals that are open while both sides next to them
+/// are closed. Two open sides next to each other make an outer corner,
+/// a lone open diagonal makes an inner corner.
+pub fn borders(mask: u8) -> impl Iterator<Item = u8> {
+    let open = |bit: u8| mask & bit == 0;
+    [N, E, S, W].into_iter().filter(move |side| open(*side)).chain(
+        [(NE, N, E), (SE, S, E), (SW, S, W), (NW, N, W)].into_iter().filter_map(
+            move |(corner, a, b)| (open(corner) && !open(a) && !open(b)).then_some(corner),
+        ),
+    )
+}
+
+/// Picks one of the options, always the same one for the same position.
+pub fn pick_variant<T: Copy>(pos: Pos, options: &[T]) -> T {
+    // splitmix64 finalizer, good enough to hide any pattern
+    let mut h = ((pos.x as u32 as u64) << 32) | pos.y as u32 as u64;
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+    h ^= h >> 31;
+    options[(h % options.len() as u64) as usize]
+}
+
+/// Sprites in the autotiles sheet for liquids that form blobs.
+/// Each family is a 12x4 block: a vertical strip, a 3x3 block with a horizontal strip
+/// below it and a block with a hole for the inner corners.
+#[derive(Debug, Clone, Copy)]
+pub enum BlobFamily {
+    Water,
+    Swamp,
+}
+
+impl BlobFamily {
+    fn first_row(self) -> i32 {
+        match self {
+            BlobFamily::Water => 0,
+            BlobFamily::Swamp => 4,
+        }
+    }
+
+    /// Position in the autotiles sheet.
+    pub fn sprite(self, mask: u8, pos: Pos) -> (i32, i32) {
+        let has = |bit: u8| mask & bit != 0;
+        let (x, y) = match (has(N), has(E), has(S), has(W)) {
+            // vertical strip
+            (false, false, true, false) => (0, 0),
+            (true, false, true, false) => pick_variant(pos, &[(0, 1), (0, 2)]),
+            (true, false, false, false) => (0, 3),
+            // horizontal strip
+            (false, true, false, false) => (1, 3),
+            (false, true, false, true) => (2, 3),
+            (false, false, false, true) => (3, 3),
+            // edges and outer corners
+            (false, true, true, false) => (1, 0),
+            (false, true, true, true) => (2, 0),
+            (false, false, true, true) => (3, 0),
+            (true, true, true, false) => (1, 1),
+            (true, false, true, true) => (3, 1),
+            (true, true, false, false) => (1, 2),
+            (true, true, false, true) => (2, 2),
+            (true, false, false, true) => (3, 2),
+            // surrounded on all sides, the diagonals decide about inner corners
+            (true, true, true, true) if !has(SE) => (9, 0),
+            (true, true, true, true) if !has(SW) => (11, 0),
+            (true, true, true, true) if !has(NE) => (9, 2),
+            (true, true, true, true) if !has(NW) => (11, 2),
+            // the sheet has no sprite for a single tile, it just gets filled
+            (true, true, true, true) | (false, false, false, false) => (2, 1),
+        };
+        (x, y + self.first_row())
+    }
+}
+
+/// Walls in the tiles sheet. Each family has a top and one or more sides,
+/// the edges and corners towards the floor are drawn as a rim over the top, see [borders].
+#[derive(Debug, Clone, Copy)]
+pub enum WallFamily {
+    Dirt,
+    StoneBrick,
+    Igneous,
+    Skull,
+}
+
+impl WallFamily {
+    /// Position in the tiles sheet.
+    /// The side of the wall faces the viewer, so it is shown when there is no wall below.
+    /// Walls that only touch other walls are solid rock.
+    pub fn sprite(self, mask: u8, pos: Pos) -> (i32, i32) {
+        let (row, sides, inner): (i32, &[i32], Option<i32>) = match self {
+            WallFamily::Dirt => (0, &[1], Some(2)),
+            WallFamily::StoneBrick => (2, &[1, 2], None),
+            WallFamily::Igneous => (3, &[1], None),
+            WallFamily::Skull => (5, &[1], None),
+        };
+        match inner {
+            Some(column) if mask == ALL => (column, row),
+            _ if mask & S != 0 => (0, row),
+            _ => (pick_variant(pos, sides), row),
+        }
+    }
+}
+
+/// Floors in the tiles sheet, mostly plain with a few stones or plants here and there.
+/// Floors along walls stay plain, the shadow of the walls is drawn over them, see [borders].
+#[derive(Debug, Clone, Copy)]
+pub enum FloorFamily {
+    Gray,
+    Dirt,
+    Stone,
+    RedStone,
+}
+
+impl FloorFamily {
+    /// Position in the tiles sheet.
+    /// `mask` has the bits of the neighbours that aren't walls.
+    pub fn sprite(self, mask: u8, pos: Pos) -> (i32, i32) {
+        // the first column of the dirt and stone rows is empty
+        let (row, plain) = match self {
+            FloorFamily::Gray => (6, 0),
+            FloorFamily::Dirt => (8, 1),
+            FloorFamily::Stone => (9, 1),
+            FloorFamily::RedStone => (11, 0),
+        };
+        if mask != ALL {
+            return (plain, row);
+        }
+        let column = pick_variant(pos, &[plain, plain, plain, plain, plain, 1, 2, 3]);
+        (column, row)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> Grid<LogicTile> {
+        let mut grid = Grid::new(rows[0].len() as i32, rows.len() as i32, LogicTile::Floor);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '~' {
+                    grid[Pos::new(x as i32, y as i32)] = LogicTile::DeepWater;
+                }
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn masks() {
+        let tiles = grid(&["~~.", "~~.", "..~"]);
+        let water = |tile| tile == LogicTile::DeepWater;
+        assert_eq!(E | S | SE, neighbor_mask(&tiles, Pos::new(0, 0), false, water));
+        assert_eq!(N | W | NW | SE, neighbor_mask(&tiles, Pos::new(1, 1), false, water));
+        let outside = neighbor_mask(&tiles, Pos::new(0, 0), true, water);
+        assert_eq!(E | S | SE | N | NE | W | NW | SW, outside);
+    }
+
+    #[test]
+    fn pool_of_water() {
+        let tiles = grid(&["~~~", "~~~", "~~~"]);
+        let sprite = |x, y| {
+            let pos = Pos::new(x, y);
+            let mask = neighbor_mask(&tiles, pos, false, |tile| tile == LogicTile::DeepWater);
+            BlobFamily::Water.sprite(mask, pos)
+        };
+        assert_eq!((1, 0), sprite(0, 0));
+        assert_eq!((2, 0), sprite(1, 0));
+        assert_eq!((3, 2), sprite(2, 2));
+        assert_eq!((2, 1), sprite(1, 1));
+
+        let tiles = grid(&["~~~", "~~~", "~~."]);
+        let mask = neighbor_mask(&tiles, Pos::new(1, 1), false, |t| t == LogicTile::DeepWater);
+        assert_eq!((9, 4), BlobFamily::Swamp.sprite(mask, Pos::new(1, 1)));
+    }
+
+    #[test]
+    fn wall_borders() {
+        let tiles = grid(&["~~~~", "~~~~", "~~~.", "...."]);
+        let wall = |tile| tile == LogicTile::DeepWater;
+        let borders_at = |x, y| {
+            let mask = neighbor_mask(&tiles, Pos::new(x, y), true, wall);
+            borders(mask).collect::<Vec<_>>()
+        };
+        // surrounded
+        assert!(borders_at(1, 0).is_empty());
+        // edge and outer corner
+        assert_eq!(vec![S], borders_at(1, 2));
+        assert_eq!(vec![E, S], borders_at(2, 2));
+        // inner corner
+        assert_eq!(vec![SE], borders_at(2, 1));
+
+        let mask = neighbor_mask(&tiles, Pos::new(1, 0), true, wall);
+        assert_eq!((2, 0), WallFamily::Dirt.sprite(mask, Pos::new(1, 0)), "solid rock");
+        assert_eq!((0, 5), WallFamily::Skull.sprite(mask, Pos::new(1, 0)), "top");
+        let mask = neighbor_mask(&tiles, Pos::new(1, 2), true, wall);
+        assert_eq!((1, 0), WallFamily::Dirt.sprite(mask, Pos::new(1, 2)), "side");
+
+        // floors along walls are plain
+        let floor_mask = neighbor_mask(&tiles, Pos::new(1, 3), true, |t| !wall(t));
+        assert_eq!((1, 8), FloorFamily::Dirt.sprite(floor_mask, Pos::new(1, 3)));
+        assert_eq!(vec![N], borders(floor_mask).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn variants_are_stable() {
+        let pos = Pos::new(3, 7);
+        let options = [1, 2, 3, 4, 5];
+        assert_eq!(pick_variant(pos, &options), pick_variant(pos, &options));
+    }
+}
@@ -1,8 +1,10 @@
+use crate::ecs_util::ensure_singleton;
 use crate::game::PendingMessage;
-use crate::game::game_ai::Pathfinding;
-use crate::game::game_logic::Actor;
+use crate::game::game_ai::PathCache;
+use crate::game::game_logic::{Actor, Player};
+use crate::game::tile_map::TileMap;
 use crate::util::world_to_game;
-use base::{ContextTrait, zone};
+use base::{ContextTrait, Pos, zone};
 use froql::{query, world::World};
 use quicksilver::{Quicksilver, reflections::reflect};
 
@@ -22,15 +24,26 @@ impl Default for DebugOptions {
 }
 
 pub fn debug_ui(c: &mut dyn ContextTrait, world: &mut World) {
+    ensure_singleton::<PathCache>(world);
     let mut s = world.singleton_mut::<DebugOptions>();
     if s.show_debug {
         zone!("show debug");
 
         {
-            let pf = Pathfinding::new(world);
+            let mut cache = world.singleton_mut::<PathCache>();
+            cache.refresh(world);
+            let tm = world.singleton::<TileMap>();
             let pos = world_to_game(c.mouse_world());
-            if let Some(val) = pf.ranged_grid.get_opt(pos) {
-                c.inspect_str(&format!("{pos:?} -> {val}"));
+            if tm.tiles.is_valid(pos) {
+                let monsters: Vec<Pos> =
+                    query!(world, Actor, !Player).map(|(a,)| a.pos).collect();
+                let melee = cache.melee()[pos];
+                let ranged = cache.ranged(&tm)[pos];
+                let flee = cache.flee()[pos];
+                let allies = cache.allies(&monsters)[pos];
+                c.inspect_str(&format!(
+                    "{pos:?} -> melee {melee}, ranged {ranged}, flee {flee}, allies {allies}"
+                ));
             }
         }
 
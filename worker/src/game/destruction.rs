@@ -0,0 +1,78 @@
+use base::{Pos, shapes, zone};
+use froql::{entity_store::Entity, world::World};
+
+use crate::{
+    animation::{self, AnimationTarget, AnimationTimer, DecorSpawnAnimation},
+    game::{
+        game_logic::TileEffect, spatial, sprites::LogicTile, tile_map::TileMap,
+        ui::log_message,
+    },
+};
+
+/// Deals `amount` damage to the terrain at `pos`, breaking it once its integrity is used up.
+/// Tiles without integrity are unbreakable, mapgen uses this to keep the level border intact.
+///
+/// Messages and debris are synced to an animation of `source` that starts at `start_time`.
+/// Returns true if the tile took damage.
+pub fn damage_terrain(
+    world: &World,
+    source: Entity,
+    pos: Pos,
+    amount: i32,
+    start_time: f32,
+) -> bool {
+    zone!();
+    let tile = {
+        let mut tm = world.singleton_mut::<TileMap>();
+        let integrity = tm.integrity.get_opt(pos).copied().unwrap_or(0);
+        if integrity <= 0 || amount <= 0 {
+            return false;
+        }
+        if integrity > amount {
+            tm.integrity[pos] = integrity - amount;
+            return true;
+        }
+        let tile = tm.tiles[pos];
+        tm.integrity[pos] = 0;
+        tm.set_tile(pos, tile.broken());
+        tile
+    };
+
+    let anim = animation::spawn_empty_animation_at(world, source, start_time, 0.1);
+    log_message(world, format!("The {} breaks apart.", tile.name()), *anim);
+    if let Some(decor) = tile.debris() {
+        world
+            .create_deferred()
+            .add(AnimationTimer::new(start_time, 0.))
+            .add(DecorSpawnAnimation { decor, pos })
+            .relate_to::<AnimationTarget>(source);
+    }
+
+    if tile == LogicTile::ExplosiveBarrel {
+        log_message(world, "The oil catches fire!".to_string(), *anim);
+        let around: Vec<Pos> = {
+            let tm = world.singleton::<TileMap>();
+            shapes::blast(pos, 1, |p| tm.blocks_vision(p))
+                .into_iter()
+                .filter(|p| tm.tiles.is_valid(*p))
+                .collect()
+        };
+        for next in around {
+            ignite(world, next);
+            // the blast can set off other barrels
+            damage_terrain(world, source, next, 1, start_time + 0.1);
+        }
+    }
+    true
+}
+
+/// Sets the tile on fire, unless it can't burn.
+fn ignite(world: &World, pos: Pos) {
+    world.defer_closure(move |world| {
+        if !world.singleton::<TileMap>().tiles[pos].can_burn() {
+            return;
+        }
+        spatial::remove_effect(world, pos);
+        spatial::add_effect(world, pos, TileEffect::Burning);
+    });
+}
@@ -1,9 +1,13 @@
 use crate::game::AnimationTarget;
+use crate::game::GameTime;
 use crate::game::game_logic::TileEffect;
-use crate::game::z_levels::Z_TILE_EFFECTS;
+use crate::game::z_levels::{
+    Z_GHOST, Z_MEMORY, Z_SCREEN_TINT, Z_TILE_BORDERS, Z_TILE_EFFECTS,
+};
 use crate::game::{
     game_logic::{Actor, Fov, Player},
-    sprites::{DrawTile, Environment, LogicTile, TILE_SIZE, generate_draw_tile},
+    lighting::LightMap,
+    sprites::{DrawTile, TILE_SIZE, draw_borders, generate_draw_tile, generate_floor_tile},
     tile_map::{DecorWithPos, TileMap},
     z_levels::{Z_DANGER_ZONE, Z_HP_BAR, Z_TILES},
 };
@@ -28,52 +32,97 @@ pub struct DangerZone {
     pub offsets: Vec<IVec>,
 }
 
+/// Darkens tiles and monsters that are remembered but not in sight
+const MEMORY_TINT: Color = Color { r: 0., g: 0., b: 0., a: 0.55 };
+
+/// Darkens a lit tile by how little light it gets and colors it like the light.
+fn light_tint(light: Color) -> [Color; 2] {
+    let intensity = light.r.max(light.g).max(light.b);
+    let darkness = Color::BLACK.alpha((1. - intensity) * 0.6);
+    let color = if intensity > 0. {
+        Color::rgba(light.r / intensity, light.g / intensity, light.b / intensity, 0.12)
+    } else {
+        Color::BLACK.alpha(0.)
+    };
+    [darkness, color]
+}
+
 pub fn draw_systems(c: &mut dyn ContextTrait, world: &World) {
     zone!();
-    let Some((fov,)) = query!(world, Fov, _ Player).next() else { return };
+    let Some((fov, player)) = query!(world, Fov, Player).next() else { return };
+
+    // tint the screen edges while rampaging
+    if let Some(color) = player.tier.tint() {
+        let time = world.singleton::<GameTime>().0;
+        let throb = 0.75 + 0.25 * (time * 8.).sin();
+        let color = color.alpha(color.a * throb);
+        let r = c.screen_rect();
+        let thickness = 30.;
+        for edge in [
+            r.take_top(thickness),
+            r.take_bot(thickness),
+            r.take_left(thickness),
+            r.take_right(thickness),
+        ] {
+            c.draw_rect(edge, color, Z_SCREEN_TINT);
+        }
+    }
 
     // draw tile map
     {
         let tm = world.singleton::<TileMap>();
-        let env = Environment::Catacomb;
-
-        for (pos, lt) in tm.tiles.iter_coords() {
-            if !fov.0.contains(&pos) {
-                continue;
+        let light = world.singleton::<LightMap>();
+        let env = tm.environment;
+
+        // remembered tiles are drawn as they were last seen
+        for &pos in &fov.explored {
+            let lt = fov.memory[pos];
+            if lt.is_on_floor() {
+                let floor = generate_floor_tile(&fov.memory, pos, env);
+                floor.draw(c, pos.to_fpos(TILE_SIZE), Z_TILES);
             }
-
-            let mut pos_below = pos.clone();
-            pos_below.y += 1;
-            let below = (&tm).tiles.get_opt(pos_below).unwrap_or(&LogicTile::Empty);
-            let draw_tile = generate_draw_tile(*lt, env, *below);
+            let draw_tile = generate_draw_tile(&fov.memory, pos, env);
             draw_tile.draw(c, pos.to_fpos(TILE_SIZE), Z_TILES);
+            draw_borders(c, &fov.memory, pos, Z_TILE_BORDERS);
+
+            let rect = pos.to_fpos(TILE_SIZE).rect(TILE_SIZE);
+            if !fov.visible.contains(&pos) {
+                c.draw_rect(rect, MEMORY_TINT, Z_MEMORY);
+            } else {
+                for color in light_tint(light.color(pos)) {
+                    c.draw_rect(rect, color, Z_MEMORY);
+                }
+            }
         }
 
         // up and down stairs
         {
             let pos = tm.up_stairs;
-            if fov.0.contains(&pos) {
+            if fov.explored.contains(&pos) {
                 DrawTile::UpStairs.draw(c, pos.to_fpos(TILE_SIZE), Z_TILES);
             }
 
             let pos = tm.down_stairs;
-            if fov.0.contains(&pos) {
+            if fov.explored.contains(&pos) {
                 DrawTile::DownStairs.draw(c, pos.to_fpos(TILE_SIZE), Z_TILES);
             }
         }
 
-        for DecorWithPos(pos, decor) in &tm.decor {
-            if !fov.0.contains(&pos) {
-                continue;
-            }
-
+        // decor and loot only ever get remembered on explored tiles
+        for DecorWithPos(pos, decor) in &fov.decor {
             decor.draw(c, pos.to_fpos(TILE_SIZE), Z_TILES);
         }
+
+        for loot in &fov.loot {
+            let sprite =
+                if loot.gold >= 50 { DrawTile::CoinsLarge } else { DrawTile::CoinsSmall };
+            sprite.draw(c, loot.pos.to_fpos(TILE_SIZE), Z_TILES);
+        }
     };
 
     // draw actors
     for (draw_health, draw_pos, actor) in query!(world, DrawHealth, DrawPos, Actor) {
-        if !fov.0.contains(&actor.pos) {
+        if !fov.sees_actor_at(actor.pos) {
             // TODO: actors shouldn't just disappear when they move outside the FOV
             // so it should be also related to draw_pos :thonk:
             continue;
@@ -96,9 +145,16 @@ pub fn draw_systems(c: &mut dyn ContextTrait, world: &World) {
         }
     }
 
+    // monsters that went out of sight are shown where they were last seen
+    for ghost in fov.hidden_ghosts() {
+        let dpos = ghost.pos.to_fpos(TILE_SIZE);
+        ghost.sprite.draw(c, dpos.x, dpos.y);
+        c.draw_rect(dpos.rect(TILE_SIZE), MEMORY_TINT, Z_GHOST);
+    }
+
     // draw tile effects
     for (pos, effect) in query!(world, Pos, TileEffect, !AnimationTarget(_, this)) {
-        if !fov.0.contains(&*pos) {
+        if !fov.visible.contains(&*pos) {
             continue;
         }
 
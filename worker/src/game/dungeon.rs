@@ -0,0 +1,274 @@
+use base::{Pos, zone};
+use froql::{entity_store::Entity, query, world::World};
+use quicksilver::Quicksilver;
+
+use crate::{
+    animation,
+    game::{
+        drawing::{DangerZone, DrawHealth, DrawPos},
+        game_ai::LastKnownTarget,
+        game_logic::{Actor, DelayedAction, Fov, Player, TileEffect},
+        mapgen::{generate_map, place_enemies},
+        sprites::pos_to_drawpos,
+        tile_map::TileMap,
+        ui::log_message,
+    },
+    rand::RandomGenerator,
+};
+
+/// All levels of the dungeon.
+/// Only the current level lives in the world, the others are stored here
+/// until the player comes back to them.
+#[derive(Debug, Quicksilver)]
+pub struct Dungeon {
+    /// The current depth, the first level has depth 1
+    pub depth: i32,
+    pub stored: Vec<StoredLevel>,
+}
+
+#[derive(Debug, Quicksilver)]
+pub struct StoredLevel {
+    pub depth: i32,
+    pub tile_map: TileMap,
+    pub actors: Vec<StoredActor>,
+    pub effects: Vec<StoredEffect>,
+    /// What the player remembers of the level
+    pub fov: Fov,
+}
+
+/// A monster on a stored level with what it was up to
+#[derive(Debug, Quicksilver)]
+pub struct StoredActor {
+    pub actor: Actor,
+    /// The attack it announced, at most one
+    pub delayed: Vec<DelayedAction>,
+    /// Where it last saw the player, at most one
+    pub last_seen: Vec<LastKnownTarget>,
+}
+
+#[derive(Debug, Quicksilver)]
+pub struct StoredEffect {
+    pub pos: Pos,
+    pub effect: TileEffect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StairDirection {
+    Up,
+    Down,
+}
+
+impl Default for Dungeon {
+    fn default() -> Self {
+        Self { depth: 1, stored: Vec::new() }
+    }
+}
+
+/// The stairs the player is standing on, if any.
+pub fn stairs_under_player(world: &World) -> Option<StairDirection> {
+    let (actor,) = query!(world, _ Player, Actor).next()?;
+    let tm = world.singleton::<TileMap>();
+    if tm.down_stairs == actor.pos {
+        Some(StairDirection::Down)
+    } else if tm.up_stairs == actor.pos {
+        Some(StairDirection::Up)
+    } else {
+        None
+    }
+}
+
+/// Moves the player to the next level in the given direction.
+/// The current level is stored in the [Dungeon] so it can be revisited later.
+/// Monsters next to the player follow them.
+///
+/// Returns the position the player arrives at,
+/// or None if there is no level in that direction.
+pub fn take_stairs(world: &mut World, direction: StairDirection) -> Option<Pos> {
+    zone!();
+    world.process();
+
+    let (player, player_pos, start_aut) = {
+        let (player, actor) = query!(world, &this, _ Player, Actor).next()?;
+        (player.entity, actor.pos, actor.next_turn)
+    };
+
+    let old_depth = world.singleton::<Dungeon>().depth;
+    let new_depth = match direction {
+        StairDirection::Down => old_depth + 1,
+        StairDirection::Up => old_depth - 1,
+    };
+    if new_depth < 1 {
+        let anim = animation::spawn_empty_animation(world, player, 0.).entity;
+        log_message(world, "The way back up is blocked.".into(), anim);
+        return None;
+    }
+
+    // monsters right next to the player follow them, everything else stays behind
+    let mut followers = Vec::new();
+    let mut left_behind = Vec::new();
+    for (e, actor) in query!(world, &this, Actor, !Player) {
+        if actor.hp.current > 0 && actor.pos.distance(player_pos) == 1 {
+            followers.push(e.entity);
+        } else {
+            left_behind.push(e.entity);
+        }
+    }
+    let mut actors = Vec::new();
+    for e in left_behind {
+        if let Some(actor) = world.take_component::<Actor>(e)
+            && actor.hp.current > 0
+        {
+            // the danger zone is made again from the delayed attack
+            let delayed = world.take_component::<DelayedAction>(e).into_iter().collect();
+            let last_seen = world.take_component::<LastKnownTarget>(e).into_iter().collect();
+            actors.push(StoredActor { actor, delayed, last_seen });
+        }
+        world.destroy(e);
+    }
+
+    let effect_entities: Vec<Entity> =
+        query!(world, &this, _ TileEffect).map(|(e,)| e.entity).collect();
+    let mut effects = Vec::new();
+    for e in effect_entities {
+        if let Some(pos) = world.take_component::<Pos>(e)
+            && let Some(effect) = world.take_component::<TileEffect>(e)
+        {
+            effects.push(StoredEffect { pos, effect });
+        }
+        world.destroy(e);
+    }
+
+    // load the level we are going to or generate a new one
+    let stored = {
+        let mut dungeon = world.singleton_mut::<Dungeon>();
+        let index = dungeon.stored.iter().position(|level| level.depth == new_depth);
+        index.map(|i| dungeon.stored.swap_remove(i))
+    };
+    let mut new_level_seed = None;
+    let (tile_map, restored_actors, restored_effects, restored_fov) = match stored {
+        Some(level) => (level.tile_map, level.actors, level.effects, level.fov),
+        None => {
+            let seed = world.singleton_mut::<RandomGenerator>().next();
+            new_level_seed = Some(seed);
+            (generate_map(seed, new_depth), Vec::new(), Vec::new(), Fov::default())
+        }
+    };
+    let arrival = match direction {
+        StairDirection::Down => tile_map.up_stairs,
+        StairDirection::Up => tile_map.down_stairs,
+    };
+
+    let old_tile_map = std::mem::replace(&mut *world.singleton_mut::<TileMap>(), tile_map);
+    let fov = world.take_component::<Fov>(player).unwrap_or_default();
+    {
+        let mut dungeon = world.singleton_mut::<Dungeon>();
+        let level =
+            StoredLevel { depth: old_depth, tile_map: old_tile_map, actors, effects, fov };
+        dungeon.stored.push(level);
+        dungeon.depth = new_depth;
+    }
+
+    // populate the level
+    if let Some(seed) = new_level_seed {
+        place_enemies(world, seed, new_depth);
+    }
+    for StoredActor { actor, delayed, last_seen } in restored_actors {
+        let e = world
+            .create()
+            .add(DrawPos(pos_to_drawpos(actor.pos)))
+            .add(DrawHealth { ratio: actor.hp.current as f32 / actor.hp.max as f32 })
+            .add(actor)
+            .entity;
+        for delayed in delayed {
+            world.add_component(e, DangerZone { offsets: delayed.offsets.clone() });
+            world.add_component(e, delayed);
+        }
+        for memory in last_seen {
+            world.add_component(e, memory);
+        }
+    }
+    for StoredEffect { pos, effect } in restored_effects {
+        world.create().add(pos).add(effect);
+    }
+
+    world.get_component_mut::<Actor>(player).pos = arrival;
+    world.add_component(player, restored_fov);
+    for (mut actor,) in query!(world, !Player, mut Actor) {
+        actor.next_turn = actor.next_turn.max(start_aut);
+    }
+    world.process();
+    TileMap::update_caches(world);
+
+    place_followers(world, &followers, arrival, old_depth);
+    world.process();
+    TileMap::update_caches(world);
+
+    Some(arrival)
+}
+
+/// Puts the followers on free tiles around the arrival point.
+/// Followers that don't fit stay behind on the old level.
+fn place_followers(world: &mut World, followers: &[Entity], arrival: Pos, old_depth: i32) {
+    zone!();
+    let mut taken = vec![arrival];
+    for &e in followers {
+        let free = {
+            let tm = world.singleton::<TileMap>();
+            arrival
+                .neighbors(&tm.tiles)
+                .find(|pos| !tm.is_blocked(*pos) && !taken.contains(pos))
+        };
+        if let Some(pos) = free {
+            taken.push(pos);
+            let mut actor = world.get_component_mut::<Actor>(e);
+            actor.pos = pos;
+            let anim = animation::spawn_empty_animation(world, e, 0.).entity;
+            log_message(world, format!("{} follows you.", actor.name), anim);
+        } else {
+            if let Some(actor) = world.take_component::<Actor>(e) {
+                let mut dungeon = world.singleton_mut::<Dungeon>();
+                if let Some(level) = dungeon.stored.iter_mut().find(|l| l.depth == old_depth) {
+                    level.actors.push(actor);
+                }
+            }
+            world.destroy(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        GameTime,
+        attacks::AttackShape,
+        game_logic::{ActionKind, create_world, handle_action},
+    };
+
+    #[test]
+    fn monsters_keep_what_they_were_up_to_between_visits() {
+        let mut world = create_world();
+        world.singleton_add(GameTime(0.));
+        let player_pos = query!(world, Actor, _ Player).next().unwrap().0.pos;
+        let (monster, pos) = query!(world, &this, Actor, !Player)
+            .map(|(e, a)| (e.entity, a.pos))
+            .find(|(_, pos)| pos.distance(player_pos) > 1)
+            .unwrap();
+        let attack = ActionKind::DelayedAttack { shape: AttackShape::Around, damage: 4 };
+        handle_action(&mut world, attack.done_by(monster));
+        let memory = LastKnownTarget { pos: player_pos, searching: false, search_turns: 5 };
+        world.add_component(monster, memory);
+        world.process();
+
+        take_stairs(&mut world, StairDirection::Down).unwrap();
+        take_stairs(&mut world, StairDirection::Up).unwrap();
+
+        let monster = query!(world, &this, Actor, !Player)
+            .find(|(_, a)| a.pos == pos)
+            .map(|(e, _)| e.entity)
+            .unwrap();
+        assert!(world.has_component::<DelayedAction>(monster));
+        assert!(world.has_component::<DangerZone>(monster));
+        assert_eq!(player_pos, world.get_component::<LastKnownTarget>(monster).pos);
+    }
+}
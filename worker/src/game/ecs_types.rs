@@ -24,12 +24,16 @@ use crate::game::debug_util::DebugOptions;
 use crate::game::drawing::DangerZone;
 use crate::game::drawing::DrawHealth;
 use crate::game::drawing::DrawPos;
+use crate::game::dungeon::Dungeon;
+use crate::game::game_ai::LastKnownTarget;
+use crate::game::game_ai::PathCache;
 use crate::game::game_logic::Actor;
 use crate::game::game_logic::DelayedAction;
 use crate::game::game_logic::Fov;
 use crate::game::game_logic::Player;
 use crate::game::game_logic::TileEffect;
 use crate::game::game_logic::TurnCount;
+use crate::game::lighting::LightMap;
 use crate::game::ui::MessageInhibitor;
 use crate::game::ui::MessageLog;
 use crate::game::ui::MessageOrder;
@@ -52,6 +56,8 @@ ecs_types!(
         Circle,
         Rect,
         GameTime,
+        PathCache,
+        LightMap,
         TileEffect[persist],
         TurnCount[persist],
         Fov[persist],
@@ -61,8 +67,10 @@ ecs_types!(
         DrawPos[persist],
         DrawHealth[persist],
         TileMap[persist],
+        Dungeon[persist],
         RandomGenerator[persist],
         DelayedAction[persist],
+        LastKnownTarget[persist],
         DangerZone[persist],
         // ui
         UI[persist],
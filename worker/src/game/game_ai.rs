@@ -1,70 +1,206 @@
-use base::{grids::Grid, zone};
+use base::{
+    Pos,
+    astar::{PathOptions, astar},
+    grids::Grid,
+    los,
+    pos::IVec,
+    zone,
+};
 use froql::{entity_store::Entity, query, world::World};
+use quicksilver::Quicksilver;
 
 use crate::{
-    dijkstra::{dijkstra, dijkstra_path},
+    dijkstra::{IMPASSABLE, dijkstra_lower_costs, dijkstra_map, dijkstra_path},
     game::{
+        attacks::AttackShape,
+        dungeon::Dungeon,
         game_logic::{Action, ActionKind, Actor, CreatureType, Player},
+        sprites::LogicTile,
         tile_map::TileMap,
     },
+    rand::RandomGenerator,
 };
 
-pub struct Pathfinding {
-    /// This is a dijkstra map for going towards the player and melee attacking them.
-    pub melee_grid: Grid<i32>,
-    pub ranged_grid: Grid<i32>,
+/// How far monsters can see the player.
+const SIGHT_RANGE: i32 = 10;
+/// How long monsters look around the last known position before giving up.
+const SEARCH_TURNS: i32 = 10;
+/// How far monsters stray from the last known position while searching.
+const SEARCH_RADIUS: i32 = 3;
+/// Goblin brutes break through terrain to get to a player that is at most this far away.
+const SMASH_RANGE: i32 = 6;
+
+/// Where a monster last saw the player.
+/// Monsters without it don't know where the player is and stay put.
+#[derive(Debug, Quicksilver)]
+pub struct LastKnownTarget {
+    pub pos: Pos,
+    /// Set once `pos` was reached and the monster looks around there
+    pub searching: bool,
+    /// Turns left to search
+    pub search_turns: i32,
+}
+
+/// Dijkstra maps shared by all monsters, kept as a singleton between turns.
+/// The maps only know about terrain, other monsters are walked around when taking a step.
+/// Maps are built when first asked for and dropped when the player moves,
+/// when terrain only got cheaper to cross they are patched instead.
+#[derive(Default)]
+pub struct PathCache {
+    /// Depth of the level the maps belong to
+    depth: i32,
+    /// Revision of the [TileMap] the maps were computed for
+    revision: u32,
+    players: Vec<Pos>,
+    /// Cost of entering each tile, [IMPASSABLE] for walls
+    costs: Option<Grid<i32>>,
+    melee: Option<Grid<i32>>,
+    ranged: Option<Grid<i32>>,
+    flee: Option<Grid<i32>>,
+    /// The ally positions the map was made for
+    allies: Option<(Vec<Pos>, Grid<i32>)>,
 }
 
-impl Pathfinding {
-    pub fn new(world: &World) -> Self {
+impl PathCache {
+    /// Drops or patches the maps that no longer fit the world.
+    pub fn refresh(&mut self, world: &World) {
         zone!();
 
         let tm = world.singleton::<TileMap>();
-        let cost_function = |pos| {
-            if tm.is_wall(pos) {
-                i32::MAX
-            } else if tm.get_actor(pos).is_some() {
-                25
+        let depth = world.singleton::<Dungeon>().depth;
+        let players: Vec<Pos> = query!(world, Actor, _ Player).map(|(a,)| a.pos).collect();
+
+        if self.costs.is_none() || self.depth != depth {
+            *self = PathCache {
+                depth,
+                revision: tm.revision,
+                players,
+                costs: Some(terrain_costs(&tm)),
+                ..Default::default()
+            };
+            return;
+        }
+
+        if self.revision != tm.revision {
+            self.revision = tm.revision;
+            let costs = terrain_costs(&tm);
+            let old = self.costs.replace(costs).unwrap();
+            let costs = self.costs.as_ref().unwrap();
+            let changed: Vec<Pos> =
+                costs.coords().into_iter().filter(|pos| costs[*pos] != old[*pos]).collect();
+
+            // the ranged map depends on line of sight, so it's always built again
+            self.ranged = None;
+            self.flee = None;
+            if changed.iter().all(|pos| costs[*pos] < old[*pos]) {
+                zone!("pathfinding: patch");
+                let cost = |pos| costs[pos];
+                if let Some(melee) = &mut self.melee {
+                    dijkstra_lower_costs(melee, &changed, cost);
+                }
+                if let Some((_, allies)) = &mut self.allies {
+                    dijkstra_lower_costs(allies, &changed, cost);
+                }
             } else {
-                1
+                self.melee = None;
+                self.allies = None;
             }
-        };
+        }
 
-        let melee_grid = {
-            let mut grid = Grid::new(tm.tiles.width, tm.tiles.height, 0);
-            let mut seeds = Vec::new();
-            for (player,) in query!(world, Actor, _ Player) {
-                grid[player.pos] = 500;
-                seeds.push(player.pos);
-            }
-            dijkstra(&mut grid, &seeds, cost_function);
-            grid
-        };
+        if self.players != players {
+            self.players = players;
+            self.melee = None;
+            self.ranged = None;
+            self.flee = None;
+        }
+    }
+
+    /// For going towards the player and melee attacking them.
+    pub fn melee(&mut self) -> &Grid<i32> {
+        let costs = self.costs.as_ref().expect("refresh first");
+        let players = &self.players;
+        self.melee.get_or_insert_with(|| {
+            zone!("pathfinding: build melee");
+            let goals: Vec<(Pos, i32)> = players.iter().map(|pos| (*pos, 0)).collect();
+            dijkstra_map(costs.width, costs.height, &goals, |pos| costs[pos])
+        })
+    }
 
-        let ranged_grid = {
-            let mut grid = Grid::new(tm.tiles.width, tm.tiles.height, 0);
-            let mut seeds = Vec::new();
-            for (player,) in query!(world, Actor, _ Player) {
-                for pos in player.pos.circle_around(5) {
-                    let has_los =
-                        pos.bresenham(player.pos).into_iter().all(|p| !tm.is_wall(p));
-                    if has_los {
-                        grid[pos] = 500;
-                        seeds.push(pos);
+    /// Distances to the tiles from where the player can be shot at.
+    pub fn ranged(&mut self, tm: &TileMap) -> &Grid<i32> {
+        let costs = self.costs.as_ref().expect("refresh first");
+        let players = &self.players;
+        self.ranged.get_or_insert_with(|| {
+            zone!("pathfinding: build ranged");
+            let mut goals = Vec::new();
+            for player in players {
+                // line of sight is symmetric, so what the player sees can shoot at them
+                let visible = los::visible_within(*player, 5, |p| tm.blocks_vision(p));
+                for pos in player.circle_around(5) {
+                    if visible.contains(&pos) && tm.is_walkable(pos) {
+                        goals.push((pos, 0));
                     }
                 }
-                dijkstra(&mut grid, &seeds, cost_function);
-                grid[player.pos] = -1; // don't move through player tile
+            }
+            let mut grid = dijkstra_map(costs.width, costs.height, &goals, |pos| costs[pos]);
+            for pos in players {
+                grid[*pos] = IMPASSABLE; // don't move through player tile
             }
             grid
-        };
+        })
+    }
+
+    /// For running away from the player.
+    /// Made from the melee map so that fleeing monsters prefer open space over dead ends.
+    pub fn flee(&mut self) -> &Grid<i32> {
+        if self.flee.is_none() {
+            let melee = self.melee();
+            zone!("pathfinding: build flee");
+            let goals: Vec<(Pos, i32)> = melee
+                .coords()
+                .into_iter()
+                .filter(|pos| melee[*pos] != IMPASSABLE)
+                .map(|pos| (pos, -(melee[pos] * 6 / 5)))
+                .collect();
+            let costs = self.costs.as_ref().unwrap();
+            self.flee =
+                Some(dijkstra_map(costs.width, costs.height, &goals, |pos| costs[pos]));
+        }
+        self.flee.as_ref().unwrap()
+    }
+
+    /// For going towards the closest of `allies`.
+    pub fn allies(&mut self, allies: &[Pos]) -> &Grid<i32> {
+        let costs = self.costs.as_ref().expect("refresh first");
+        if self.allies.as_ref().is_none_or(|(positions, _)| positions != allies) {
+            zone!("pathfinding: build allies");
+            let goals: Vec<(Pos, i32)> = allies.iter().map(|pos| (*pos, 0)).collect();
+            let grid = dijkstra_map(costs.width, costs.height, &goals, |pos| costs[pos]);
+            self.allies = Some((allies.to_vec(), grid));
+        }
+        &self.allies.as_ref().unwrap().1
+    }
+}
 
-        Self { melee_grid, ranged_grid }
+fn terrain_costs(tm: &TileMap) -> Grid<i32> {
+    zone!();
+    let mut costs = Grid::new(tm.tiles.width, tm.tiles.height, IMPASSABLE);
+    for pos in tm.tiles.coords() {
+        let tile = tm.tiles[pos];
+        costs[pos] = if !tile.is_passable() {
+            IMPASSABLE
+        } else if tile == LogicTile::DoorClosed {
+            // opening the door takes a turn
+            2
+        } else {
+            (tile.move_cost() / 10) as i32
+        };
     }
+    costs
 }
 
 #[must_use]
-pub fn ai_turn(world: &World, pf: &Pathfinding, npc: Entity) -> Action {
+pub fn ai_turn(world: &World, npc: Entity) -> Action {
     zone!();
 
     let actor = world.get_component::<Actor>(npc);
@@ -80,8 +216,51 @@ pub fn ai_turn(world: &World, pf: &Pathfinding, npc: Entity) -> Action {
                 // goblin brutes smash the player if they are in range
                 for (player_a,) in query!(world, Actor, _ Player) {
                     if actor.pos.distance(player_a.pos) == 1 {
-                        return ActionKind::DelayedSmash { dir: player_a.pos - actor.pos }
-                            .done_by(npc);
+                        let shape = AttackShape::Single { dir: player_a.pos - actor.pos };
+                        return ActionKind::DelayedAttack { shape, damage: 3 }.done_by(npc);
+                    }
+                }
+                // and smash through walls and props between them and where they know
+                // the player to be
+                let tm = world.singleton::<TileMap>();
+                let seen = query!(world, Actor, _ Player)
+                    .map(|(player_a,)| player_a.pos)
+                    .find(|pos| sees(&tm, actor.pos, *pos));
+                let target = seen.or_else(|| {
+                    world.get_component_mut_opt::<LastKnownTarget>(npc).map(|m| m.pos)
+                });
+                if let Some(target) = target
+                    && actor.pos.distance(target) <= SMASH_RANGE
+                {
+                    let line = los::line_of_fire(actor.pos, target, |p| tm.is_blocked(p));
+                    if let Some(&pos) = line.get(1)
+                        && tm.integrity.get_opt(pos).is_some_and(|i| *i > 0)
+                    {
+                        let shape = AttackShape::Single { dir: pos - actor.pos };
+                        return ActionKind::DelayedAttack { shape, damage: 3 }.done_by(npc);
+                    }
+                }
+            }
+            CreatureType::OrcWizard
+            | CreatureType::OrcWarchief
+            | CreatureType::Troll
+            | CreatureType::Ettin => {
+                // big monsters telegraph area attacks when the player would be hit by them
+                for (player_a,) in query!(world, Actor, _ Player) {
+                    let dir = player_a.pos - actor.pos;
+                    let (shape, damage) = match actor.creature_type {
+                        CreatureType::OrcWizard => (AttackShape::Line { dir, length: 6 }, 3),
+                        CreatureType::OrcWarchief => {
+                            let dir = IVec::new(dir.x.signum(), dir.y.signum());
+                            (AttackShape::Cone { dir, length: 2 }, 3)
+                        }
+                        CreatureType::Troll => (AttackShape::Around, 4),
+                        CreatureType::Ettin => (AttackShape::Ring { radius: 2 }, 4),
+                        _ => unreachable!(),
+                    };
+                    let tm = world.singleton::<TileMap>();
+                    if shape.footprint(actor.pos, &tm).contains(&player_a.pos) {
+                        return ActionKind::DelayedAttack { shape, damage }.done_by(npc);
                     }
                 }
             }
@@ -93,10 +272,10 @@ pub fn ai_turn(world: &World, pf: &Pathfinding, npc: Entity) -> Action {
                     let distance = actor.pos.distance(player_a.pos);
                     if distance > 1 && distance <= 5 {
                         let tm = world.singleton::<TileMap>();
-                        let path = actor.pos.bresenham(player_a.pos);
-                        let blocked =
-                            path[1..(path.len() - 1)].iter().any(|p| tm.is_blocked(*p));
-                        if !blocked {
+                        let blocked = |p| tm.is_blocked(p);
+                        let path = los::line_of_fire(actor.pos, player_a.pos, blocked);
+                        if los::first_blocker(&path, blocked).is_none_or(|p| p == player_a.pos)
+                        {
                             return match actor.creature_type {
                                 CreatureType::GoblinArcher => {
                                     ActionKind::ShootArrow { path, target: *player_e }
@@ -120,31 +299,299 @@ pub fn ai_turn(world: &World, pf: &Pathfinding, npc: Entity) -> Action {
         }
     }
 
-    // set up pathfinding dijsktra map
+    // only a player in sight is chased, otherwise go looking for them
+    if !sees_player(world, actor.pos) {
+        return search(world, npc, actor.pos);
+    }
+
+    // pick the dijkstra map
+    let tm = world.singleton::<TileMap>();
+    let mut cache = world.singleton_mut::<PathCache>();
+    cache.refresh(world);
     let grid = match actor.creature_type {
-        CreatureType::GoblinArcher | CreatureType::GoblinMage => &pf.ranged_grid,
-        _ => &pf.melee_grid,
+        CreatureType::GoblinArcher | CreatureType::GoblinMage | CreatureType::OrcWizard => {
+            cache.ranged(&tm)
+        }
+        _ => cache.melee(),
     };
-    let tm = world.singleton::<TileMap>();
 
     // do pathfinding
     let start = actor.pos;
-    let path = dijkstra_path(&grid, start);
+    let path = dijkstra_path(grid, start);
+    let Some(&next) = path.get(1) else {
+        return ActionKind::Wait.done_by(npc);
+    };
 
-    if path.len() > 1
-        && let Some(next) = path[1..].first()
-        && !tm.is_blocked(*next)
-    {
-        return ActionKind::Move { from: start, to: *next }.done_by(npc);
-    } else if path.len() > 1
-        && let Some(next) = path[1..].first()
-        && let Some(target) = tm.get_actor(*next)
-        && world.has_component::<Player>(target)
-    {
-        // attack player if the player is the thing blocking movement
-        return ActionKind::BumpAttack { target }.done_by(npc);
+    if let Some(target) = tm.get_actor(next) {
+        if world.has_component::<Player>(target) {
+            // attack player if the player is the thing blocking movement
+            return ActionKind::BumpAttack { target }.done_by(npc);
+        }
+        // another monster is in the way, step around it if that still gets us closer
+        let around = start
+            .neighbors(&tm.tiles)
+            .filter(|pos| grid[*pos] < grid[start] && !tm.is_blocked(*pos))
+            .min_by_key(|pos| grid[*pos]);
+        return match around {
+            Some(to) => ActionKind::Move { from: start, to }.done_by(npc),
+            None => ActionKind::Wait.done_by(npc),
+        };
+    }
+
+    if tm.tiles[next] == LogicTile::DoorClosed {
+        ActionKind::OpenDoor { pos: next }.done_by(npc)
+    } else if !tm.is_blocked(next) {
+        ActionKind::Move { from: start, to: next }.done_by(npc)
     } else {
         // just stand in place
+        ActionKind::Wait.done_by(npc)
+    }
+}
+
+fn sees(tm: &TileMap, from: Pos, to: Pos) -> bool {
+    from.distance(to) <= SIGHT_RANGE && los::has_los(from, to, |p| tm.blocks_vision(p))
+}
+
+fn sees_player(world: &World, from: Pos) -> bool {
+    let tm = world.singleton::<TileMap>();
+    query!(world, Actor, _ Player).any(|(player_a,)| sees(&tm, from, player_a.pos))
+}
+
+/// Updates where `npc` last saw the player, called after each of its actions.
+pub fn remember_target(world: &mut World, npc: Entity) {
+    zone!();
+    let seen = {
+        let tm = world.singleton::<TileMap>();
+        let pos = world.get_component::<Actor>(npc).pos;
+        query!(world, Actor, _ Player)
+            .map(|(player_a,)| player_a.pos)
+            .find(|player_pos| sees(&tm, pos, *player_pos))
+    };
+    if let Some(pos) = seen {
+        let memory = LastKnownTarget { pos, searching: false, search_turns: SEARCH_TURNS };
+        world.add_component(npc, memory);
+    }
+}
+
+/// Goes to where the player was last seen, then wanders around there for a while.
+fn search(world: &World, npc: Entity, start: Pos) -> Action {
+    zone!();
+    let Some(mut memory) = world.get_component_mut_opt::<LastKnownTarget>(npc) else {
         return ActionKind::Wait.done_by(npc);
+    };
+    let tm = world.singleton::<TileMap>();
+
+    if !memory.searching && start != memory.pos {
+        let path = astar(
+            &tm.tiles,
+            start,
+            memory.pos,
+            PathOptions::default(),
+            |_, tile| tile.is_passable(),
+            |_, tile| tile.move_cost() as i32,
+        );
+        match path.map(|path| path[1]) {
+            Some(next) if tm.tiles[next] == LogicTile::DoorClosed => {
+                return ActionKind::OpenDoor { pos: next }.done_by(npc);
+            }
+            Some(next) if !tm.is_blocked(next) => {
+                return ActionKind::Move { from: start, to: next }.done_by(npc);
+            }
+            Some(_) => {
+                // someone is in the way, step around them if that still gets us closer
+                let goal = memory.pos;
+                let around = start
+                    .neighbors(&tm.tiles)
+                    .filter(|pos| pos.distance(goal) < start.distance(goal))
+                    .filter(|pos| !tm.is_blocked(*pos))
+                    .min_by_key(|pos| pos.distance(goal));
+                return match around {
+                    Some(to) => ActionKind::Move { from: start, to }.done_by(npc),
+                    None => ActionKind::Wait.done_by(npc),
+                };
+            }
+            // can't get there, so search from here instead
+            None => memory.pos = start,
+        }
+    }
+    memory.searching = true;
+
+    if memory.search_turns <= 0 {
+        world.view_deferred(npc).remove::<LastKnownTarget>();
+        return ActionKind::Wait.done_by(npc);
+    }
+    memory.search_turns -= 1;
+    let mut rand = world.singleton_mut::<RandomGenerator>();
+    for _ in 0..8 {
+        let to = start + rand.random_direction();
+        if !tm.is_blocked(to) && to.distance(memory.pos) <= SEARCH_RADIUS {
+            return ActionKind::Move { from: start, to }.done_by(npc);
+        }
+    }
+    ActionKind::Wait.done_by(npc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        GameTime,
+        game_logic::{DelayedAction, handle_action, handle_delayed_action},
+        register_components, spatial,
+    };
+
+    /// A world with just the level, actors are added with [spawn].
+    fn test_world(tm: TileMap) -> World {
+        let mut world = World::new();
+        register_components(&mut world);
+        world.singleton_add(tm);
+        world.singleton_add(GameTime(0.));
+        world.singleton_add(RandomGenerator::new(1));
+        world.singleton_add(PathCache::default());
+        world
+    }
+
+    fn spawn(world: &mut World, creature: CreatureType, pos: Pos) -> Entity {
+        let e = creature.create_deferred(world, pos).entity;
+        world.process();
+        TileMap::update_caches(world);
+        e
+    }
+
+    #[test]
+    fn brutes_smash_through_props() {
+        let mut tm = TileMap::new(9, 3, LogicTile::Floor);
+        tm.enwall();
+        let crate_pos = Pos::new(4, 1);
+        tm.set_tile(crate_pos, LogicTile::Crate);
+        tm.integrity[crate_pos] = 5;
+        let mut world = test_world(tm);
+        spawn(&mut world, CreatureType::PlayerCharacter, Pos::new(7, 1));
+        let brute = spawn(&mut world, CreatureType::GoblinBrute, Pos::new(3, 1));
+
+        let action = ai_turn(&world, brute);
+        let ActionKind::DelayedAttack { shape: AttackShape::Single { dir }, .. } = action.kind
+        else {
+            panic!("expected a smash, got {action:?}");
+        };
+        assert_eq!(IVec::new(1, 0), dir);
+
+        handle_action(&mut world, action);
+        let delayed = world.take_component::<DelayedAction>(brute).unwrap();
+        handle_delayed_action(&world, brute, delayed);
+        world.process();
+        assert_eq!(2, world.singleton::<TileMap>().integrity[crate_pos]);
+    }
+
+    #[test]
+    fn brutes_only_dig_towards_players_they_know_about() {
+        let mut tm = TileMap::new(9, 3, LogicTile::Floor);
+        tm.enwall();
+        let wall = Pos::new(4, 1);
+        tm.set_tile(wall, LogicTile::Wall);
+        tm.integrity[wall] = 3;
+        let mut world = test_world(tm);
+        spawn(&mut world, CreatureType::PlayerCharacter, Pos::new(7, 1));
+        let brute = spawn(&mut world, CreatureType::GoblinBrute, Pos::new(3, 1));
+
+        let action = ai_turn(&world, brute);
+        assert!(!matches!(action.kind, ActionKind::DelayedAttack { .. }), "{action:?}");
+
+        remember(&mut world, brute, Pos::new(6, 1));
+        let action = ai_turn(&world, brute);
+        let ActionKind::DelayedAttack { shape: AttackShape::Single { dir }, .. } = action.kind
+        else {
+            panic!("expected a smash, got {action:?}");
+        };
+        assert_eq!(wall, Pos::new(3, 1) + dir);
+    }
+
+    /// Runs the turn of `npc`, only moves are carried out.
+    fn take_turn(world: &mut World, npc: Entity) -> Action {
+        let action = ai_turn(world, npc);
+        if let ActionKind::Move { to, .. } = action.kind {
+            let mut actor = world.get_component_mut::<Actor>(npc);
+            spatial::move_actor(world, npc, &mut actor, to);
+        }
+        world.process();
+        action
+    }
+
+    fn remember(world: &mut World, npc: Entity, pos: Pos) {
+        let memory = LastKnownTarget { pos, searching: false, search_turns: SEARCH_TURNS };
+        world.add_component(npc, memory);
+    }
+
+    #[test]
+    fn monsters_search_where_they_last_saw_the_player() {
+        let mut tm = TileMap::new(12, 3, LogicTile::Floor);
+        tm.enwall();
+        let mut world = test_world(tm);
+        let goblin = spawn(&mut world, CreatureType::Goblin, Pos::new(1, 1));
+        let last_seen = Pos::new(5, 1);
+        remember(&mut world, goblin, last_seen);
+
+        for _ in 0..4 {
+            let action = take_turn(&mut world, goblin);
+            assert!(matches!(action.kind, ActionKind::Move { .. }), "{action:?}");
+        }
+        assert_eq!(last_seen, world.get_component::<Actor>(goblin).pos);
+
+        take_turn(&mut world, goblin);
+        let memory = world.get_component::<LastKnownTarget>(goblin);
+        assert!(memory.searching);
+        assert_eq!(SEARCH_TURNS - 1, memory.search_turns);
+        let pos = world.get_component::<Actor>(goblin).pos;
+        assert!(pos.distance(last_seen) <= SEARCH_RADIUS);
+    }
+
+    #[test]
+    fn monsters_give_up_searching() {
+        let mut tm = TileMap::new(9, 5, LogicTile::Floor);
+        tm.enwall();
+        let mut world = test_world(tm);
+        let goblin = spawn(&mut world, CreatureType::Goblin, Pos::new(4, 2));
+        remember(&mut world, goblin, Pos::new(4, 2));
+
+        for _ in 0..SEARCH_TURNS {
+            take_turn(&mut world, goblin);
+            assert!(world.has_component::<LastKnownTarget>(goblin));
+        }
+        let action = take_turn(&mut world, goblin);
+        assert!(matches!(action.kind, ActionKind::Wait), "{action:?}");
+        assert!(!world.has_component::<LastKnownTarget>(goblin));
+    }
+
+    #[test]
+    fn searching_monsters_wait_for_allies_in_the_way() {
+        let mut tm = TileMap::new(9, 3, LogicTile::Floor);
+        tm.enwall();
+        let mut world = test_world(tm);
+        let goblin = spawn(&mut world, CreatureType::Goblin, Pos::new(1, 1));
+        spawn(&mut world, CreatureType::Goblin, Pos::new(2, 1));
+        let last_seen = Pos::new(6, 1);
+        remember(&mut world, goblin, last_seen);
+
+        let action = take_turn(&mut world, goblin);
+        assert!(matches!(action.kind, ActionKind::Wait), "{action:?}");
+        let memory = world.get_component::<LastKnownTarget>(goblin);
+        assert_eq!(last_seen, memory.pos, "the ally doesn't make the spot unreachable");
+        assert!(!memory.searching);
+    }
+
+    #[test]
+    fn searching_monsters_step_around_allies() {
+        let mut tm = TileMap::new(9, 5, LogicTile::Floor);
+        tm.enwall();
+        let mut world = test_world(tm);
+        let goblin = spawn(&mut world, CreatureType::Goblin, Pos::new(1, 2));
+        spawn(&mut world, CreatureType::Goblin, Pos::new(2, 2));
+        remember(&mut world, goblin, Pos::new(6, 2));
+
+        let action = take_turn(&mut world, goblin);
+        let ActionKind::Move { to, .. } = action.kind else {
+            panic!("expected a move, got {action:?}");
+        };
+        assert_eq!(2, to.x);
     }
 }
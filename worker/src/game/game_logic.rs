@@ -1,11 +1,20 @@
 use crate::{
-    animation::{AnimationTarget, AnimationTimer, MovementAnimation},
-    game::{drawing::DangerZone, tile_map::TileMap},
+    animation::{AnimationTarget, AnimationTimer, BUMP_ATTACK_LENGTH, MovementAnimation},
+    game::{
+        PlayerAbility,
+        attacks::AttackShape,
+        destruction::damage_terrain,
+        drawing::DangerZone,
+        knockback::knockback,
+        lighting::{LightMap, VISIBLE_LIGHT},
+        spatial::{self, Layer},
+        tile_map::{DecorWithPos, Loot, TileMap},
+    },
     quicksilver_glue::EntityWrapper,
 };
 use std::collections::HashSet;
 
-use base::{FPos, Pos, pos::IVec, zone};
+use base::{Color, FPos, Pos, grids::Grid, pos::IVec, shadowcasting, zone};
 use froql::{
     entity_store::Entity, entity_view_deferred::EntityViewDeferred, query, world::World,
 };
@@ -15,8 +24,9 @@ use crate::{
     animation::{self, AnimationCleanup, HPBarAnimation},
     game::{
         drawing::{DrawHealth, DrawPos},
+        dungeon::Dungeon,
         mapgen::{generate_map, place_enemies},
-        sprites::{CreatureSprite, DrawTile},
+        sprites::{CreatureSprite, DrawTile, LogicTile},
         ui::{MessageLog, log_message},
         {UI, register_components},
     },
@@ -34,12 +44,191 @@ pub struct Actor {
     pub next_turn: i64,
 }
 
+/// Maximum amount of rage the player can build up
+pub const RAGE_MAX: i32 = 20;
+/// Rage gained when hitting or getting hit
+const RAGE_PER_HIT: i32 = 2;
+/// While the pulse is at least this high every action builds up rage
+const RAGE_PULSE_THRESHOLD: f32 = 75.;
+/// Above this pulse every action of the player damages their heart
+const HEART_STRAIN_PULSE: f32 = 130.;
+/// How far a kick sends its target flying
+const KICK_DISTANCE: i32 = 2;
+/// Kills that are at most this many aut apart form a kill chain
+const COMBO_WINDOW: i64 = 30;
+/// Extra pulse per kill in a kill chain
+const COMBO_PULSE: f32 = 4.;
+
 /// Marker for player character
 #[derive(Debug, Quicksilver)]
 pub struct Player {
     pub pulse: f32,
     /// aut of the last pulse raising action taken
     pub last_pulse_action: i64,
+    /// resource spent on abilities
+    pub rage: i32,
+    pub cooldowns: Vec<AbilityCooldown>,
+    /// the tier the player was in after their last action
+    pub tier: PulseTier,
+    pub kills: i32,
+    /// aut of the last kill
+    pub last_kill: i64,
+    /// length of the current kill chain
+    pub combo: i32,
+    pub gold: i32,
+}
+
+/// Rampage mode: the higher the pulse, the stronger (and riskier) the player gets.
+#[derive(Debug, Quicksilver, Clone, Copy, PartialEq, PartialOrd, Default)]
+#[repr(C)]
+pub enum PulseTier {
+    #[default]
+    Calm,
+    Frenzy,
+    Rampage,
+    Berserk,
+}
+
+impl PulseTier {
+    pub fn from_pulse(pulse: f32) -> Self {
+        if pulse >= 120. {
+            PulseTier::Berserk
+        } else if pulse >= 100. {
+            PulseTier::Rampage
+        } else if pulse >= 80. {
+            PulseTier::Frenzy
+        } else {
+            PulseTier::Calm
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PulseTier::Calm => "Calm",
+            PulseTier::Frenzy => "Frenzy",
+            PulseTier::Rampage => "Rampage",
+            PulseTier::Berserk => "Berserk",
+        }
+    }
+
+    /// extra damage on every attack
+    pub fn bonus_damage(self) -> i32 {
+        match self {
+            PulseTier::Calm => 0,
+            PulseTier::Frenzy => 1,
+            PulseTier::Rampage => 2,
+            PulseTier::Berserk => 3,
+        }
+    }
+
+    /// aut saved on every action
+    pub fn haste(self) -> i64 {
+        match self {
+            PulseTier::Calm | PulseTier::Frenzy => 0,
+            PulseTier::Rampage => 1,
+            PulseTier::Berserk => 2,
+        }
+    }
+
+    /// every melee hit pushes the target away
+    pub fn knockback(self) -> bool {
+        self >= PulseTier::Rampage
+    }
+
+    /// damage ignored on every hit taken
+    pub fn pain_reduction(self) -> i32 {
+        match self {
+            PulseTier::Berserk => 1,
+            _ => 0,
+        }
+    }
+
+    /// color of the screen edge while in this tier
+    pub fn tint(self) -> Option<Color> {
+        match self {
+            PulseTier::Calm => None,
+            PulseTier::Frenzy => Some(Color::rgba(1.0, 0.6, 0.0, 0.25)),
+            PulseTier::Rampage => Some(Color::rgba(1.0, 0.1, 0.0, 0.3)),
+            PulseTier::Berserk => Some(Color::rgba(0.6, 0.0, 0.2, 0.4)),
+        }
+    }
+
+    fn enter_message(self) -> &'static str {
+        match self {
+            PulseTier::Calm => "Your heartbeat settles down.",
+            PulseTier::Frenzy => "Your blood starts boiling. FRENZY!",
+            PulseTier::Rampage => "You go on a RAMPAGE!",
+            PulseTier::Berserk => "You go BERSERK! Your heart pounds dangerously.",
+        }
+    }
+}
+
+#[derive(Debug, Quicksilver)]
+pub struct AbilityCooldown {
+    pub ability: PlayerAbility,
+    /// aut at which the ability can be used again
+    pub ready_at: i64,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Self {
+            pulse: 60.,
+            last_pulse_action: 0,
+            rage: 5,
+            cooldowns: Vec::new(),
+            tier: PulseTier::Calm,
+            kills: 0,
+            last_kill: 0,
+            combo: 0,
+            gold: 0,
+        }
+    }
+
+    /// aut at which the ability can be used again
+    pub fn ready_at(&self, ability: PlayerAbility) -> i64 {
+        self.cooldowns
+            .iter()
+            .find(|cd| cd.ability == ability)
+            .map(|cd| cd.ready_at)
+            .unwrap_or(0)
+    }
+
+    /// Returns the reason why the ability can't be used right now, if there is one.
+    pub fn check_ability(&self, ability: PlayerAbility, now: i64) -> Result<(), String> {
+        let wait = self.ready_at(ability) - now;
+        if wait > 0 {
+            return Err(format!(
+                "{} needs {:.1} more turns to recover.",
+                ability.name(),
+                wait as f32 / 10.
+            ));
+        }
+        let cost = ability.rage_cost();
+        if self.rage < cost {
+            return Err(format!(
+                "You are not angry enough for {}. ({}/{cost} rage)",
+                ability.name(),
+                self.rage
+            ));
+        }
+        Ok(())
+    }
+
+    /// Pays the rage cost and starts the cooldown.
+    pub fn use_ability(&mut self, ability: PlayerAbility, now: i64) {
+        self.rage = (self.rage - ability.rage_cost()).max(0);
+        let ready_at = now + ability.cooldown();
+        if let Some(cd) = self.cooldowns.iter_mut().find(|cd| cd.ability == ability) {
+            cd.ready_at = ready_at;
+        } else {
+            self.cooldowns.push(AbilityCooldown { ability, ready_at });
+        }
+    }
+
+    pub fn gain_rage(&mut self, amount: i32) {
+        self.rage = (self.rage + amount).min(RAGE_MAX);
+    }
 }
 
 #[derive(Debug, Quicksilver)]
@@ -70,9 +259,103 @@ pub struct TurnCount {
     pub aut: i64,
 }
 
-/// Set of Positions within the sight of an actor.
+/// What the player sees right now and what they remember of the level.
+#[derive(Debug, Quicksilver, Default)]
+pub struct Fov {
+    /// Tiles within sight right now
+    pub visible: HashSet<Pos>,
+    /// Every tile that was ever seen on this level, the visible ones included
+    pub explored: HashSet<Pos>,
+    /// The terrain as it looked when it was last seen, [LogicTile::Empty] where unexplored
+    pub memory: Grid<LogicTile>,
+    /// Decor and loot on explored tiles as they were last seen
+    pub decor: Vec<DecorWithPos>,
+    pub loot: Vec<Loot>,
+    /// Monsters that went out of sight, where they were last seen
+    pub ghosts: Vec<Ghost>,
+    /// Visible tiles with a monster on them that is too well hidden in the dark to be spotted
+    pub hidden: HashSet<Pos>,
+}
+
 #[derive(Debug, Quicksilver)]
-pub struct Fov(pub HashSet<Pos>);
+pub struct Ghost {
+    #[quicksilver(proxy(Entity, EntityWrapper))]
+    pub entity: Entity,
+    pub pos: Pos,
+    pub sprite: CreatureSprite,
+}
+
+impl Fov {
+    /// Recomputes what is visible from `from` and updates the memory.
+    /// Tiles have to be in line of sight and lit to be visible.
+    /// `monsters` are all actors that could be seen.
+    pub fn update(
+        &mut self,
+        from: Pos,
+        tm: &TileMap,
+        light: &LightMap,
+        monsters: impl Iterator<Item = (Entity, Pos, CreatureSprite, f32)>,
+    ) {
+        zone!();
+        // nothing is known about a new level
+        if self.memory.width != tm.tiles.width || self.memory.height != tm.tiles.height {
+            self.memory = Grid::new(tm.tiles.width, tm.tiles.height, LogicTile::Empty);
+        }
+
+        self.visible.clear();
+        shadowcasting::compute_fov(from, &mut |pos| tm.blocks_vision(pos), &mut |pos| {
+            if light.intensity(pos) >= VISIBLE_LIGHT {
+                self.visible.insert(pos);
+            }
+        });
+        // your own tile is always visible
+        self.visible.insert(from);
+        for pos in &self.visible {
+            if let Some(tile) = tm.tiles.get_opt(*pos) {
+                self.memory[*pos] = *tile;
+                self.explored.insert(*pos);
+            }
+        }
+        self.decor.retain(|DecorWithPos(pos, _)| !self.visible.contains(pos));
+        for DecorWithPos(pos, decor) in &tm.decor {
+            if self.visible.contains(pos) {
+                self.decor.push(DecorWithPos(*pos, *decor));
+            }
+        }
+        self.loot.retain(|loot| !self.visible.contains(&loot.pos));
+        self.loot.extend(tm.loot.iter().filter(|loot| self.visible.contains(&loot.pos)));
+
+        let mut seen = Vec::new();
+        self.hidden.clear();
+        for (entity, pos, sprite, stealth) in monsters {
+            if !self.visible.contains(&pos) {
+                continue;
+            }
+            if light.intensity(pos) < VISIBLE_LIGHT + stealth {
+                self.hidden.insert(pos);
+                continue;
+            }
+            seen.push(entity);
+            let ghost = Ghost { entity, pos, sprite };
+            match self.ghosts.iter_mut().find(|g| g.entity == entity) {
+                Some(old) => *old = ghost,
+                None => self.ghosts.push(ghost),
+            }
+        }
+        // looking at the spot reveals that the monster is gone
+        self.ghosts.retain(|g| seen.contains(&g.entity) || !self.visible.contains(&g.pos));
+    }
+
+    /// Ghosts of monsters that are out of sight right now
+    pub fn hidden_ghosts(&self) -> impl Iterator<Item = &Ghost> {
+        self.ghosts.iter().filter(|g| !self.sees_actor_at(g.pos))
+    }
+
+    /// If an actor standing on `pos` is seen.
+    pub fn sees_actor_at(&self, pos: Pos) -> bool {
+        self.visible.contains(&pos) && !self.hidden.contains(&pos)
+    }
+}
 
 /// Anything an actor may do
 #[derive(Debug, Quicksilver)]
@@ -82,9 +365,14 @@ pub struct Action {
     pub kind: ActionKind,
 }
 
+/// An attack that was announced and hits on the next turn of the actor.
 #[derive(Debug, Quicksilver)]
 pub struct DelayedAction {
-    pub action: Action,
+    pub shape: AttackShape,
+    pub damage: i32,
+    /// The tiles shown in the [DangerZone], relative to the actor.
+    /// Exactly these are hit, even if the terrain changed in between.
+    pub offsets: Vec<IVec>,
 }
 
 #[derive(Debug, Quicksilver)]
@@ -124,8 +412,21 @@ pub enum ActionKind {
         #[quicksilver(proxy(Entity, EntityWrapper))]
         target: Entity,
     },
-    DelayedSmash {
-        dir: IVec,
+    OpenDoor {
+        pos: Pos,
+    },
+    /// Breaks a door, leaving rubble behind
+    KickDoor {
+        pos: Pos,
+    },
+    /// Hits a crate or barrel
+    Smash {
+        pos: Pos,
+    },
+    /// Telegraphs an attack that hits on the next turn of the actor
+    DelayedAttack {
+        shape: AttackShape,
+        damage: i32,
     },
 }
 
@@ -133,6 +434,18 @@ impl ActionKind {
     pub fn done_by(self, actor: Entity) -> Action {
         Action { actor, kind: self }
     }
+
+    /// The player ability this action corresponds to, if any.
+    pub fn player_ability(&self) -> Option<PlayerAbility> {
+        match self {
+            ActionKind::Meditate => Some(PlayerAbility::Meditate),
+            ActionKind::GroundSlam => Some(PlayerAbility::GroundSlam),
+            ActionKind::RockThrow { .. } => Some(PlayerAbility::ThrowRock),
+            ActionKind::JumpAttack { .. } => Some(PlayerAbility::JumpAttack),
+            ActionKind::Kick { .. } | ActionKind::KickDoor { .. } => Some(PlayerAbility::Kick),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Quicksilver, Copy, Clone)]
@@ -141,7 +454,7 @@ pub enum TileEffect {
     Burning,
 }
 
-#[derive(Debug, Quicksilver, Copy, Clone)]
+#[derive(Debug, Quicksilver, Copy, Clone, PartialEq)]
 #[repr(C)]
 pub enum CreatureType {
     PlayerCharacter,
@@ -150,9 +463,38 @@ pub enum CreatureType {
     GoblinArcher,
     GoblinMage,
     OrcFighter,
+    OrcWizard,
+    OrcWarchief,
+    Troll,
+    Ettin,
 }
 
 impl CreatureType {
+    /// How much of the difficulty budget of a level this creature uses up.
+    pub fn spawn_cost(self) -> i32 {
+        match self {
+            CreatureType::PlayerCharacter => 0,
+            CreatureType::Goblin => 1,
+            CreatureType::GoblinArcher => 2,
+            CreatureType::GoblinMage => 3,
+            CreatureType::GoblinBrute => 3,
+            CreatureType::OrcFighter => 3,
+            CreatureType::OrcWizard => 4,
+            CreatureType::OrcWarchief => 6,
+            CreatureType::Troll => 7,
+            CreatureType::Ettin => 8,
+        }
+    }
+
+    /// Extra light needed on the tile of this creature for it to be spotted.
+    pub fn stealth(self) -> f32 {
+        match self {
+            CreatureType::Goblin | CreatureType::GoblinArcher => 0.25,
+            CreatureType::GoblinMage => 0.1,
+            _ => 0.,
+        }
+    }
+
     pub fn create_deferred(self, world: &World, pos: Pos) -> EntityViewDeferred<'_> {
         let e = world.create_deferred();
         e.add(DrawPos(FPos::new(0., 0.)));
@@ -167,8 +509,8 @@ impl CreatureType {
                     hp: HP::new(30),
                     next_turn: 0,
                 });
-                e.add(Player { pulse: 60., last_pulse_action: 0 });
-                e.add(Fov(HashSet::new()));
+                e.add(Player::new());
+                e.add(Fov::default());
             }
             CreatureType::Goblin => {
                 e.add(Actor {
@@ -220,12 +562,59 @@ impl CreatureType {
                     next_turn: 0,
                 });
             }
+            CreatureType::OrcWizard => {
+                e.add(Actor {
+                    name: "Orc Wizard".into(),
+                    pos,
+                    creature_type: self,
+                    sprite: CreatureSprite::OrcWizard,
+                    hp: HP::new(8),
+                    next_turn: 0,
+                });
+            }
+            CreatureType::OrcWarchief => {
+                e.add(Actor {
+                    name: "Orc Warchief".into(),
+                    pos,
+                    creature_type: self,
+                    sprite: CreatureSprite::OrcWarchief,
+                    hp: HP::new(16),
+                    next_turn: 0,
+                });
+            }
+            CreatureType::Troll => {
+                e.add(Actor {
+                    name: "Troll".into(),
+                    pos,
+                    creature_type: self,
+                    sprite: CreatureSprite::Troll,
+                    hp: HP::new(20),
+                    next_turn: 0,
+                });
+            }
+            CreatureType::Ettin => {
+                e.add(Actor {
+                    name: "Ettin".into(),
+                    pos,
+                    creature_type: self,
+                    sprite: CreatureSprite::Ettin,
+                    hp: HP::new(18),
+                    next_turn: 0,
+                });
+            }
         }
         e
     }
 }
 
-pub fn handle_death(world: &World, target: Entity, target_a: &Actor, animation: Entity) {
+/// `killer` is whoever caused the damage, only deaths caused by the player count as kills.
+pub fn handle_death(
+    world: &World,
+    killer: Option<Entity>,
+    target: Entity,
+    target_a: &Actor,
+    animation: Entity,
+) {
     zone!();
     if target_a.hp.current <= 0 {
         let msg = format!("{} dies.", target_a.name);
@@ -235,6 +624,11 @@ pub fn handle_death(world: &World, target: Entity, target_a: &Actor, animation:
             animation::spawn_game_over_animation(world, target);
         } else {
             world.view_deferred(target).relate_from::<AnimationCleanup>(animation);
+            if let Some(mut player) =
+                killer.and_then(|k| world.get_component_mut_opt::<Player>(k))
+            {
+                player.kills += 1;
+            }
         }
     }
 }
@@ -244,6 +638,7 @@ pub fn raise_pulse(world: &World, e: Entity, e_actor: &Actor) {
     if let Some(mut player) = world.get_component_mut_opt::<Player>(e) {
         player.pulse += 3.0;
         player.last_pulse_action = e_actor.next_turn;
+        player.gain_rage(RAGE_PER_HIT);
     };
 }
 
@@ -271,7 +666,7 @@ pub fn lower_pulse(world: &World, e: Entity, e_actor: &mut Actor) {
             let a = animation::spawn_empty_animation(world, e, 0.).entity;
             log_message(world, "You die of cardiac arrest.".to_string(), a);
             e_actor.hp.current = -9999;
-            handle_death(world, e, &e_actor, a);
+            handle_death(world, None, e, &e_actor, a);
         }
     }
 }
@@ -279,11 +674,22 @@ pub fn lower_pulse(world: &World, e: Entity, e_actor: &mut Actor) {
 pub fn handle_action(world: &mut World, action: Action) {
     zone!();
     world.process();
+    let actor_e = action.actor;
+    let start_aut = world.get_component::<Actor>(actor_e).next_turn;
+    let kills_before = world.get_component_mut_opt::<Player>(actor_e).map(|p| p.kills);
+
+    // abilities cost rage and go on cooldown
+    if let Some(ability) = action.kind.player_ability()
+        && let Some(mut player) = world.get_component_mut_opt::<Player>(actor_e)
+    {
+        player.use_ability(ability, start_aut);
+    }
+
     match action {
         Action { actor, kind: ActionKind::Wait } => {
             let mut actor_a = world.get_component_mut::<Actor>(actor);
             lower_pulse(world, actor, &mut actor_a);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
         }
         Action { actor, kind: ActionKind::Meditate } => {
             let mut actor_a = world.get_component_mut::<Actor>(actor);
@@ -296,23 +702,63 @@ pub fn handle_action(world: &mut World, action: Action) {
                 animation::spawn_empty_animation(world, actor, 0.5).add(hp_change).entity;
             let msg = format!("{} meditates briefly and heals for {heal} HP.", actor_a.name);
             log_message(world, msg, anim);
-            actor_a.next_turn += 50;
+            actor_a.next_turn += action_cost(world, actor, 50);
         }
         Action { actor, kind: ActionKind::Move { from, to } } => {
             let anim = animation::spawn_move_animation(world, actor, from, to);
-            world.get_component_mut::<Actor>(actor).pos = to;
+            let mut actor_a = world.get_component_mut::<Actor>(actor);
+            spatial::move_actor(world, actor, &mut actor_a, to);
             if world.has_component::<Player>(actor) {
                 animation::add_camera_move(world, anim, to);
             }
+            lower_pulse(world, actor, &mut actor_a);
+            let move_cost = world.singleton::<TileMap>().tiles[to].move_cost();
+            actor_a.next_turn += action_cost(world, actor, move_cost);
+            if let Some(mut player) = world.get_component_mut_opt::<Player>(actor) {
+                let loot = world.singleton_mut::<TileMap>().take_loot(to);
+                if let Some(gold) = loot {
+                    player.gold += gold;
+                    log_message(world, format!("You pick up {gold} gold."), anim);
+                }
+            }
+        }
+        Action { actor, kind: ActionKind::OpenDoor { pos } } => {
+            world.singleton_mut::<TileMap>().set_tile(pos, LogicTile::DoorOpen);
             let mut actor_a = world.get_component_mut::<Actor>(actor);
+            let animation = animation::spawn_empty_animation(world, actor, 0.1);
+            log_message(world, format!("{} opens the door.", actor_a.name), *animation);
             lower_pulse(world, actor, &mut actor_a);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
+        }
+        Action { actor, kind: ActionKind::KickDoor { pos } } => {
+            {
+                let mut tm = world.singleton_mut::<TileMap>();
+                let broken = tm.tiles[pos].broken();
+                tm.set_tile(pos, broken);
+            }
+            let mut actor_a = world.get_component_mut::<Actor>(actor);
+            let start = animation::start_time(world, &[actor]);
+            let animation =
+                animation::spawn_bump_animation_at(world, actor, actor_a.pos, pos, start);
+            let msg = format!("{} kicks the door off its hinges.", actor_a.name);
+            log_message(world, msg, animation);
+            raise_pulse(world, actor, &actor_a);
+            actor_a.next_turn += action_cost(world, actor, 10);
+        }
+        Action { actor, kind: ActionKind::Smash { pos } } => {
+            let mut actor_a = world.get_component_mut::<Actor>(actor);
+            let start = animation::start_time(world, &[actor]);
+            animation::spawn_bump_animation_at(world, actor, actor_a.pos, pos, start);
+            damage_terrain(world, actor, pos, 1, start + BUMP_ATTACK_LENGTH / 2.);
+            lower_pulse(world, actor, &mut actor_a);
+            actor_a.next_turn += action_cost(world, actor, 10);
         }
         Action { actor, kind: ActionKind::BumpAttack { target } } => {
             assert_ne!(actor, target);
             let mut actor_a = world.get_component_mut::<Actor>(actor);
             let mut target_a = world.get_component_mut::<Actor>(target);
-            let hp_bar_change = target_a.hp.dmg(3);
+            let bump_start = animation::start_time(world, &[actor, target]);
+            let hp_bar_change = target_a.hp.dmg(compute_damage(world, actor, target, 3));
             let animation = animation::spawn_bump_attack_animation(
                 world,
                 actor,
@@ -326,12 +772,19 @@ pub fn handle_action(world: &mut World, action: Action) {
 
             raise_pulse(world, actor, &actor_a);
             raise_pulse(world, target, &target_a);
-            handle_death(world, target, &target_a, animation);
-            actor_a.next_turn += 10;
+            handle_death(world, Some(actor), target, &target_a, animation);
+
+            // rampaging knocks back on every hit
+            if pulse_tier(world, actor).is_some_and(|tier| tier.knockback()) {
+                let dir = target_a.pos - actor_a.pos;
+                drop(target_a);
+                knockback(world, actor, target, dir, 1, bump_start + BUMP_ATTACK_LENGTH);
+            }
+            actor_a.next_turn += action_cost(world, actor, 10);
         }
         Action { actor, kind: ActionKind::RockThrow { path, target } } => {
             let mut target_a = world.get_component_mut::<Actor>(target);
-            let hp_bar_change = target_a.hp.dmg(2);
+            let hp_bar_change = target_a.hp.dmg(compute_damage(world, actor, target, 2));
             let animation = animation::spawn_projectile_animation(
                 world,
                 DrawTile::Rock,
@@ -344,13 +797,13 @@ pub fn handle_action(world: &mut World, action: Action) {
             let msg = format!("{} throws a huge rock at {}.", actor_a.name, target_a.name);
             log_message(world, msg, animation);
             raise_pulse(world, target, &target_a);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
 
-            handle_death(world, target, &target_a, animation);
+            handle_death(world, Some(actor), target, &target_a, animation);
         }
         Action { actor, kind: ActionKind::ShootArrow { path, target } } => {
             let mut target_a = world.get_component_mut::<Actor>(target);
-            let hp_bar_change = target_a.hp.dmg(2);
+            let hp_bar_change = target_a.hp.dmg(compute_damage(world, actor, target, 2));
             let animation = animation::spawn_projectile_animation(
                 world,
                 DrawTile::Arrow,
@@ -363,13 +816,13 @@ pub fn handle_action(world: &mut World, action: Action) {
             let msg = format!("{} shoots an arrow at {}.", actor_a.name, target_a.name);
             log_message(world, msg, animation);
             raise_pulse(world, target, &target_a);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
 
-            handle_death(world, target, &target_a, animation);
+            handle_death(world, Some(actor), target, &target_a, animation);
         }
         Action { actor, kind: ActionKind::ShootFire { path, target } } => {
             let mut target_a = world.get_component_mut::<Actor>(target);
-            let hp_bar_change = target_a.hp.dmg(2);
+            let hp_bar_change = target_a.hp.dmg(compute_damage(world, actor, target, 2));
             let effect_pos = path.last().copied().unwrap();
             let animation = animation::spawn_projectile_animation(
                 world,
@@ -383,12 +836,13 @@ pub fn handle_action(world: &mut World, action: Action) {
             // so that we can grab its animation timer
             world.defer_closure(move |world| {
                 // remove previous effect on tile
-                if let Some(e) = { world.singleton::<TileMap>().get_effect(effect_pos) } {
-                    world.destroy(e);
+                spatial::remove_effect(world, effect_pos);
+                // water puts the fire out
+                if !world.singleton::<TileMap>().tiles[effect_pos].can_burn() {
+                    return;
                 }
                 let end_time = world.get_component::<AnimationTimer>(animation).end;
-                let tile_effect =
-                    world.create().add(effect_pos).add(TileEffect::Burning).entity;
+                let tile_effect = spatial::add_effect(world, effect_pos, TileEffect::Burning);
                 // this animation is just here to supress the display of the tile effect
                 // until after the projectile hits
                 world
@@ -401,9 +855,9 @@ pub fn handle_action(world: &mut World, action: Action) {
             let msg = format!("{} throws fire at {}.", actor_a.name, target_a.name);
             log_message(world, msg, animation);
             raise_pulse(world, target, &target_a);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
 
-            handle_death(world, target, &target_a, animation);
+            handle_death(world, Some(actor), target, &target_a, animation);
         }
         Action { actor, kind: ActionKind::JumpAttack { path, target } } => {
             let mut actor_a = world.get_component_mut::<Actor>(actor);
@@ -422,10 +876,10 @@ pub fn handle_action(world: &mut World, action: Action) {
                 .create_deferred()
                 .add(AnimationTimer::new(animation_start, jump_length))
                 .relate_to::<AnimationTarget>(target);
-            actor_a.pos = jump_pos;
+            spatial::move_actor(world, actor, &mut actor_a, jump_pos);
 
             // hp change
-            let hp_change = target_a.hp.dmg(4);
+            let hp_change = target_a.hp.dmg(compute_damage(world, actor, target, 4));
             let hp_anim = world
                 .create_deferred()
                 .add(hp_change)
@@ -437,19 +891,18 @@ pub fn handle_action(world: &mut World, action: Action) {
             log_message(world, msg, *jump_ani);
             raise_pulse(world, actor, &actor_a);
             raise_pulse(world, target, &target_a);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
 
-            handle_death(world, target, &target_a, hp_anim);
+            handle_death(world, Some(actor), target, &target_a, hp_anim);
         }
         Action { actor, kind: ActionKind::Kick { target } } => {
             assert_ne!(actor, target);
             let mut actor_a = world.get_component_mut::<Actor>(actor);
             let mut target_a = world.get_component_mut::<Actor>(target);
             let dir = target_a.pos - actor_a.pos;
-            let kicked_to = target_a.pos + dir;
-            let tm = world.singleton::<TileMap>();
+            let kick_start = animation::start_time(world, &[actor, target]);
 
-            let hp_bar_change = target_a.hp.dmg(3);
+            let hp_bar_change = target_a.hp.dmg(compute_damage(world, actor, target, 3));
             let kick_ani = animation::spawn_bump_attack_animation(
                 world,
                 actor,
@@ -461,98 +914,225 @@ pub fn handle_action(world: &mut World, action: Action) {
             let msg = format!("{} kicks {}.", actor_a.name, target_a.name);
             log_message(world, msg, kick_ani);
 
-            if !tm.is_blocked(kicked_to) {
-                let _fly_ani =
-                    animation::spawn_move_animation(world, target, target_a.pos, kicked_to);
-                target_a.pos = kicked_to;
-                let msg = format!("{} flies away.", target_a.name);
-                log_message(world, msg, kick_ani);
-            }
-
             raise_pulse(world, actor, &actor_a);
             raise_pulse(world, target, &target_a);
-            handle_death(world, target, &target_a, kick_ani);
-            actor_a.next_turn += 10;
+            handle_death(world, Some(actor), target, &target_a, kick_ani);
+            drop(target_a);
+
+            let push_start = kick_start + BUMP_ATTACK_LENGTH;
+            knockback(world, actor, target, dir, KICK_DISTANCE, push_start);
+            actor_a.next_turn += action_cost(world, actor, 10);
         }
         Action { actor, kind: ActionKind::GroundSlam } => {
             let animation = animation::spawn_camera_shake_animation(world);
             animation.relate_to::<AnimationTarget>(actor);
 
-            // find enemies around player and damage them
-            let tm = world.singleton::<TileMap>();
+            // find enemies around player, damage them and push them away
             let mut actor_a = world.get_component_mut::<Actor>(actor);
             let epicenter = actor_a.pos;
+            let targets: Vec<Entity> = {
+                let tm = world.singleton::<TileMap>();
+                tm.index
+                    .within(Layer::Actors, epicenter, 1)
+                    .filter(|(pos, _)| *pos != epicenter)
+                    .map(|(_, e)| e)
+                    .collect()
+            };
 
             let msg = format!("{} stomps the ground mightily.", actor_a.name);
             log_message(world, msg, *animation);
 
-            for pos in epicenter.neighbors(&tm.tiles) {
-                if let Some(target) = tm.get_actor(pos) {
-                    animation.relate_to::<AnimationTarget>(target);
-                    let mut target_a = world.get_component_mut::<Actor>(target);
+            for target in targets {
+                animation.relate_to::<AnimationTarget>(target);
+                let mut target_a = world.get_component_mut::<Actor>(target);
 
-                    let hp_change = target_a.hp.dmg(2);
-                    let hp_anim = animation::spawn_empty_animation(world, target, 0.3);
-                    hp_anim.add(hp_change);
+                let hp_start = animation::start_time(world, &[target]);
+                let hp_change = target_a.hp.dmg(compute_damage(world, actor, target, 2));
+                let hp_anim = animation::spawn_empty_animation(world, target, 0.3);
+                hp_anim.add(hp_change);
 
-                    raise_pulse(world, actor, &actor_a);
-                    raise_pulse(world, target, &target_a);
-                    handle_death(world, target, &target_a, *hp_anim);
-                }
+                raise_pulse(world, actor, &actor_a);
+                raise_pulse(world, target, &target_a);
+                handle_death(world, Some(actor), target, &target_a, *hp_anim);
+
+                let dir = target_a.pos - epicenter;
+                drop(target_a);
+                knockback(world, actor, target, dir, 1, hp_start + 0.3);
             }
 
-            actor_a.next_turn += 10;
+            // the walls around shake too
+            let slam_start = animation::start_time(world, &[actor]);
+            let around: Vec<Pos> =
+                epicenter.neighbors(&world.singleton::<TileMap>().tiles).collect();
+            for pos in around {
+                damage_terrain(world, actor, pos, 2, slam_start);
+            }
+
+            actor_a.next_turn += action_cost(world, actor, 10);
         }
-        Action { actor, kind: ActionKind::DelayedSmash { dir } } => {
-            world.add_component(actor, DelayedAction { action });
+        Action { actor, kind: ActionKind::DelayedAttack { shape, damage } } => {
+            let offsets: Vec<IVec> = {
+                let pos = world.get_component::<Actor>(actor).pos;
+                let footprint = shape.footprint(pos, &world.singleton::<TileMap>());
+                footprint.into_iter().map(|p| p - pos).collect()
+            };
+            let delayed = DelayedAction { shape, damage, offsets: offsets.clone() };
+            world.add_component(actor, delayed);
             let mut actor_a = world.get_component_mut::<Actor>(actor);
-            let dz = DangerZone { offsets: vec![dir] };
+            let dz = DangerZone { offsets };
             let animation = animation::spawn_add_dangerzone_animation(world, actor, dz);
-            let msg = format!("{} prepares to smash.", actor_a.name);
+            let msg = format!("{} prepares a {}.", actor_a.name, shape.name());
             log_message(world, msg, animation);
-            actor_a.next_turn += 10;
+            actor_a.next_turn += action_cost(world, actor, 10);
         }
     };
+
+    // a racing heart makes the player angry
+    if let Some(mut player) = world.get_component_mut_opt::<Player>(actor_e)
+        && player.pulse >= RAGE_PULSE_THRESHOLD
+    {
+        player.gain_rage(1);
+    }
+
+    if let Some(kills_before) = kills_before {
+        update_rampage(world, actor_e, kills_before, start_aut);
+    }
     world.process();
 }
 
-pub fn handle_delayed_action(world: &World, action: Action) {
+/// Handles kill chains, heart strain and changes of the pulse tier.
+/// Called after every action of the player.
+fn update_rampage(world: &World, e: Entity, kills_before: i32, start_aut: i64) {
     zone!();
+    let mut player = world.get_component_mut::<Player>(e);
+    let mut actor = world.get_component_mut::<Actor>(e);
 
-    match action {
-        Action { actor, kind: ActionKind::DelayedSmash { dir } } => {
-            let tm = world.singleton::<TileMap>();
-            let mut actor_a = world.get_component_mut::<Actor>(actor);
-            let target_pos = actor_a.pos + dir;
+    let new_kills = player.kills - kills_before;
+    if new_kills > 0 {
+        if player.combo > 0 && start_aut - player.last_kill <= COMBO_WINDOW {
+            player.combo += new_kills;
+        } else {
+            player.combo = new_kills;
+        }
+        player.last_kill = start_aut;
+        if player.combo > 1 {
+            player.pulse += COMBO_PULSE * (player.combo - 1) as f32;
+            let a = animation::spawn_empty_animation(world, e, 0.).entity;
+            let msg = format!("{}x kill chain! Your heart races.", player.combo);
+            log_message(world, msg, a);
+        }
+    }
 
-            if let Some(target) = tm.get_actor(target_pos) {
-                assert_ne!(actor, target);
-                let mut target_a = world.get_component_mut::<Actor>(target);
-                let hp_bar_change = target_a.hp.dmg(3);
-                animation::spawn_remove_dangerzone_animation(world, actor);
-                let animation = animation::spawn_bump_attack_animation(
-                    world,
-                    actor,
-                    target,
-                    actor_a.pos,
-                    target_a.pos,
-                    hp_bar_change,
-                );
-                let msg = format!("{} smashes {}.", actor_a.name, target_a.name);
-                log_message(world, msg, animation);
+    if player.pulse >= HEART_STRAIN_PULSE && actor.hp.current > 0 {
+        let hp_change = actor.hp.dmg(1);
+        let a = animation::spawn_empty_animation(world, e, 0.2).add(hp_change).entity;
+        log_message(world, "Your heart strains under the pressure.".to_string(), a);
+        handle_death(world, None, e, &actor, a);
+    }
 
-                raise_pulse(world, actor, &actor_a);
-                raise_pulse(world, target, &target_a);
-                handle_death(world, target, &target_a, animation);
-            } else {
-                let animation = animation::spawn_remove_dangerzone_animation(world, actor);
-                let msg = format!("{} smashes nothing.", actor_a.name);
-                log_message(world, msg, animation);
-            }
-            actor_a.next_turn += 10;
+    let tier = PulseTier::from_pulse(player.pulse);
+    if tier != player.tier {
+        player.tier = tier;
+        let anim = animation::spawn_camera_shake_animation(world);
+        anim.relate_to::<AnimationTarget>(e);
+        log_message(world, tier.enter_message().to_string(), *anim);
+    }
+}
+
+/// The pulse tier of the entity, if it is the player.
+fn pulse_tier(world: &World, e: Entity) -> Option<PulseTier> {
+    if world.has_component::<Player>(e) {
+        Some(world.get_component::<Player>(e).tier)
+    } else {
+        None
+    }
+}
+
+/// Attack damage after the bonuses of the attacker.
+pub fn damage_dealt(world: &World, attacker: Entity, base: i32) -> i32 {
+    base + pulse_tier(world, attacker).map_or(0, |tier| tier.bonus_damage())
+}
+
+/// Damage after the target shrugged off what it could.
+pub fn damage_taken(world: &World, target: Entity, dmg: i32) -> i32 {
+    let reduction = pulse_tier(world, target).map_or(0, |tier| tier.pain_reduction());
+    (dmg - reduction).max(1)
+}
+
+pub fn compute_damage(world: &World, attacker: Entity, target: Entity, base: i32) -> i32 {
+    damage_taken(world, target, damage_dealt(world, attacker, base))
+}
+
+/// How many aut an action takes for the actor.
+pub fn action_cost(world: &World, actor: Entity, base: i64) -> i64 {
+    base - pulse_tier(world, actor).map_or(0, |tier| tier.haste())
+}
+
+/// Resolves the attack `actor` announced on its last turn.
+pub fn handle_delayed_action(world: &World, actor: Entity, delayed: DelayedAction) {
+    zone!();
+
+    let DelayedAction { shape, damage, offsets } = delayed;
+    let mut actor_a = world.get_component_mut::<Actor>(actor);
+    let targets: Vec<Entity> = {
+        let tm = world.singleton::<TileMap>();
+        offsets
+            .iter()
+            .filter_map(|offset| tm.get_actor(actor_a.pos + *offset))
+            .filter(|e| *e != actor)
+            .collect()
+    };
+    let animation = animation::spawn_remove_dangerzone_animation(world, actor);
+
+    let mut hit_something = false;
+    for target in targets {
+        let mut target_a = world.get_component_mut::<Actor>(target);
+        if target_a.hp.current <= 0 {
+            continue;
+        }
+        hit_something = true;
+        let hp_bar_change = target_a.hp.dmg(compute_damage(world, actor, target, damage));
+        let animation = if let AttackShape::Single { .. } = shape {
+            animation::spawn_bump_attack_animation(
+                world,
+                actor,
+                target,
+                actor_a.pos,
+                target_a.pos,
+                hp_bar_change,
+            )
+        } else {
+            // everything in the area gets hit at once
+            let start = animation::start_time(world, &[actor]);
+            animation::spawn_hp_bar_animation_at(world, target, hp_bar_change, start, 0.3)
+        };
+        let msg = format!("{}'s {} hits {}.", actor_a.name, shape.name(), target_a.name);
+        log_message(world, msg, animation);
+
+        raise_pulse(world, actor, &actor_a);
+        raise_pulse(world, target, &target_a);
+        handle_death(world, Some(actor), target, &target_a, animation);
+    }
+    // smashes also break walls and props
+    if let AttackShape::Single { dir } = shape {
+        let pos = actor_a.pos + dir;
+        let tile = {
+            let tm = world.singleton::<TileMap>();
+            tm.integrity.get_opt(pos).is_some_and(|i| *i > 0).then(|| tm.tiles[pos])
+        };
+        if let Some(tile) = tile {
+            hit_something = true;
+            let msg = format!("{}'s {} hits the {}.", actor_a.name, shape.name(), tile.name());
+            log_message(world, msg, animation);
+            let start = animation::start_time(world, &[actor]);
+            damage_terrain(world, actor, pos, damage, start);
         }
-        other => panic!("Unhandled delayed action: {other:?}"),
     }
+    if !hit_something {
+        let msg = format!("{}'s {} hits nothing.", actor_a.name, shape.name());
+        log_message(world, msg, animation);
+    }
+    actor_a.next_turn += 10;
 }
 
 pub fn next_turn_actor(world: &World) -> Option<Entity> {
@@ -574,20 +1154,109 @@ pub fn create_world() -> World {
     register_components(&mut world);
 
     // TODO get properly random seed
-    let tm = generate_map(12345);
+    let tm = generate_map(12345, 1);
 
     CreatureType::PlayerCharacter.create_deferred(&world, tm.up_stairs);
     world.process();
 
     world.singleton_add(tm);
+    world.singleton_add(Dungeon::default());
 
     world.singleton_add(UI::default());
     world.singleton_add(TurnCount { aut: 0 });
     world.singleton_add(MessageLog::default());
-    place_enemies(&mut world, 12345);
+    place_enemies(&mut world, 12345, 1);
 
     world.singleton_add(RandomGenerator::new(12345));
 
     world.process();
     world
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameTime, lighting::LightSource};
+
+    #[test]
+    fn fov_remembers_explored_tiles() {
+        let mut tm = TileMap::new(10, 3, LogicTile::Floor);
+        tm.enwall();
+        tm.set_tile(Pos::new(5, 1), LogicTile::DoorOpen);
+        tm.loot.push(Loot { pos: Pos::new(8, 1), gold: 10 });
+        let mut fov = Fov::default();
+        let light = LightMap::default(); // everything is lit
+
+        fov.update(Pos::new(2, 1), &tm, &light, std::iter::empty());
+        assert!(fov.visible.contains(&Pos::new(8, 1)));
+        assert_eq!(fov.visible, fov.explored);
+        assert_eq!(1, fov.loot.len());
+
+        // closing the door hides the other side, but it's still remembered
+        tm.set_tile(Pos::new(5, 1), LogicTile::DoorClosed);
+        fov.update(Pos::new(2, 1), &tm, &light, std::iter::empty());
+        assert!(!fov.visible.contains(&Pos::new(8, 1)));
+        assert!(fov.explored.contains(&Pos::new(8, 1)));
+        assert_eq!(LogicTile::DoorClosed, fov.memory[Pos::new(5, 1)]);
+
+        // changes out of sight aren't noticed
+        tm.set_tile(Pos::new(7, 1), LogicTile::Wall);
+        tm.loot.clear();
+        fov.update(Pos::new(2, 1), &tm, &light, std::iter::empty());
+        assert_eq!(LogicTile::Floor, fov.memory[Pos::new(7, 1)]);
+        assert_eq!(1, fov.loot.len(), "the gold is still remembered");
+
+        // until the spot is seen again
+        tm.set_tile(Pos::new(5, 1), LogicTile::DoorOpen);
+        fov.update(Pos::new(2, 1), &tm, &light, std::iter::empty());
+        assert!(fov.loot.is_empty());
+    }
+
+    #[test]
+    fn fov_needs_light() {
+        let mut tm = TileMap::new(10, 3, LogicTile::Floor);
+        tm.enwall();
+        let mut light =
+            LightMap { light: Grid::new(10, 3, Color::BLACK), ..Default::default() };
+        light.add_light(&tm, Pos::new(7, 1), LightSource { radius: 1, color: Color::WHITE });
+        let mut fov = Fov::default();
+
+        fov.update(Pos::new(1, 1), &tm, &light, std::iter::empty());
+        assert!(fov.visible.contains(&Pos::new(1, 1)), "own tile");
+        assert!(!fov.visible.contains(&Pos::new(3, 1)), "dark");
+        assert_eq!(LogicTile::Empty, fov.memory[Pos::new(3, 1)], "unexplored");
+        assert!(fov.visible.contains(&Pos::new(7, 1)), "lit");
+        assert!(fov.explored.contains(&Pos::new(7, 1)));
+    }
+
+    #[test]
+    fn delayed_attacks_hit_the_announced_tiles() {
+        let mut world = World::new();
+        register_components(&mut world);
+        let mut tm = TileMap::new(9, 3, LogicTile::Floor);
+        tm.enwall();
+        tm.set_tile(Pos::new(3, 1), LogicTile::DoorClosed);
+        world.singleton_add(tm);
+        world.singleton_add(GameTime(0.));
+        let wizard = CreatureType::OrcWizard.create_deferred(&world, Pos::new(1, 1)).entity;
+        let player =
+            CreatureType::PlayerCharacter.create_deferred(&world, Pos::new(5, 1)).entity;
+        world.process();
+        TileMap::update_caches(&mut world);
+
+        let shape = AttackShape::Line { dir: IVec::new(1, 0), length: 6 };
+        handle_action(
+            &mut world,
+            ActionKind::DelayedAttack { shape, damage: 3 }.done_by(wizard),
+        );
+        // the door opens before the blast goes off
+        world.singleton_mut::<TileMap>().set_tile(Pos::new(3, 1), LogicTile::DoorOpen);
+        let delayed = world.take_component::<DelayedAction>(wizard).unwrap();
+        assert_eq!(vec![IVec::new(1, 0)], delayed.offsets);
+        handle_delayed_action(&world, wizard, delayed);
+        world.process();
+
+        let player_a = world.get_component::<Actor>(player);
+        assert_eq!(player_a.hp.max, player_a.hp.current, "outside of the danger zone");
+    }
+}
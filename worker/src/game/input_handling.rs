@@ -1,23 +1,22 @@
-use crate::game::TileEffect;
+use crate::game::ui::log_message;
 use crate::{
-    animation::CameraMoveAnimation,
+    animation::{self, CameraMoveAnimation},
     ecs_util::ensure_singleton,
     game::{
         AbilityUIState, PlayerAbility, UI, UIState,
         drawing::DrawPos,
+        dungeon::{stairs_under_player, take_stairs},
         game_logic::{ActionKind, Actor, Fov, Player},
-        mapgen::{generate_map, place_enemies},
-        sprites::{TILE_DIM, TILE_SIZE, pos_to_drawpos},
+        sprites::{LogicTile, TILE_DIM, TILE_SIZE, pos_to_drawpos},
         tile_map::TileMap,
         z_levels::{Z_AVY_LABEL, Z_CURSOR},
     },
-    rand::RandomGenerator,
 };
 use base::{
-    Color, ContextTrait, FVec, Input, Pos, Rect, TextProperty, pos::IVec, text::Labelize, zone,
+    Color, ContextTrait, FVec, Input, Pos, Rect, TextProperty, los, pos::IVec, text::Labelize,
+    zone,
 };
 use froql::{entity_store::Entity, query, world::World};
-use std::collections::HashSet;
 
 use super::game_logic::Action;
 
@@ -31,14 +30,7 @@ pub fn player_inputs(c: &mut dyn ContextTrait, world: &mut World) -> Option<Acti
         world.singleton_mut::<UI>().state = UIState::Ability;
         ensure_singleton::<AbilityUIState>(world);
         let mut state = world.singleton_mut::<AbilityUIState>();
-        let ability = match nr {
-            1 => PlayerAbility::ThrowRock,
-            2 => PlayerAbility::Kick,
-            3 => PlayerAbility::GroundSlam,
-            4 => PlayerAbility::JumpAttack,
-            5 => PlayerAbility::Meditate,
-            _ => PlayerAbility::ThrowRock,
-        };
+        let ability = PlayerAbility::HOTKEYS.get(nr - 1).copied().unwrap_or_default();
         state.ability_selected = ability;
         return None;
     }
@@ -53,54 +45,25 @@ pub fn player_inputs(c: &mut dyn ContextTrait, world: &mut World) -> Option<Acti
         return None;
     }
 
-    // go down the stairs
+    // take the stairs
     if c.is_pressed(Input::Confirm)
-        && let Some((player, mut p_actor)) = query!(world, &this, _ Player, mut Actor).next()
-        && world.singleton::<TileMap>().down_stairs == p_actor.pos
+        && let Some(direction) = stairs_under_player(world)
     {
-        let player = player.entity;
-
-        // clean up NPCs on old level
-        for (e,) in query!(world, !Player, _ Actor, &this) {
-            e.destroy();
-        }
-
-        // clean up tile effects
-        for (e,) in query!(world, _ TileEffect, &this) {
-            e.destroy();
-        }
-
-        // generate new level and place player there
-        let mut rand = world.singleton_mut::<RandomGenerator>();
-        let seed = rand.next();
-        let tm = generate_map(seed);
-        p_actor.pos = tm.up_stairs;
-        let start_aut = p_actor.next_turn;
-
-        // remove existing camera move animations
-        for (anim,) in query!(world, &this, _ CameraMoveAnimation) {
-            anim.destroy();
-        }
-
-        // move camera to center on player
-        let from = c.screen_rect_world().center();
-        let goal = {
-            let p = pos_to_drawpos(tm.up_stairs);
-            Rect::new_center_wh(p, TILE_DIM, TILE_DIM).center()
-        };
-        let offset = goal - from;
-        dbg!(&offset);
-        c.camera_move_rel(offset);
-
-        world.defer_closure(move |world| {
-            world.singleton_add(tm);
-            place_enemies(world, seed);
-            for (mut actor,) in query!(world, !Player, mut Actor) {
-                actor.next_turn = start_aut;
+        if let Some(arrival) = take_stairs(world, direction) {
+            // remove existing camera move animations
+            for (anim,) in query!(world, &this, _ CameraMoveAnimation) {
+                anim.destroy();
             }
-            world.add_component(player, Fov(HashSet::new()));
-        });
 
+            // move camera to center on player
+            let from = c.screen_rect_world().center();
+            let goal = {
+                let p = pos_to_drawpos(arrival);
+                Rect::new_center_wh(p, TILE_DIM, TILE_DIM).center()
+            };
+            let offset = goal - from;
+            c.camera_move_rel(offset);
+        }
         return None;
     }
 
@@ -183,6 +146,13 @@ fn player_direction_input(c: &mut dyn ContextTrait, world: &mut World) -> Option
                     actor: *e,
                     kind: ActionKind::Move { from: player.pos, to: new_pos },
                 });
+            } else if tm.tiles.get_opt(new_pos).is_some_and(|tile| tile.is_prop()) {
+                return Some(Action { actor: *e, kind: ActionKind::Smash { pos: new_pos } });
+            } else if tm.tiles.get_opt(new_pos) == Some(&LogicTile::DoorClosed) {
+                return Some(Action {
+                    actor: *e,
+                    kind: ActionKind::OpenDoor { pos: new_pos },
+                });
             } else {
                 if let Some(other_e) = tm.get_actor(new_pos) {
                     return Some(Action {
@@ -226,6 +196,19 @@ fn ability_input(c: &mut dyn ContextTrait, world: &mut World) -> Option<Action>
         return None;
     };
 
+    // reject abilities on cooldown or without enough rage
+    let problem = {
+        let player_p = world.get_component::<Player>(player);
+        let now = world.get_component::<Actor>(player).next_turn;
+        player_p.check_ability(ability, now).err()
+    };
+    if let Some(msg) = problem {
+        let anim = animation::spawn_empty_animation(world, player, 0.).entity;
+        log_message(world, msg, anim);
+        exit_ability_state(world);
+        return None;
+    }
+
     return match ability {
         PlayerAbility::ThrowRock => ability_input_line(c, world, 1, 5, |path, target| {
             ActionKind::RockThrow { path, target }
@@ -263,7 +246,7 @@ fn ability_input_line(
     if state.cursor_pos.is_none() {
         let mut positions = Vec::new();
         for (actor, _draw_pos) in query!(world, Actor, DrawPos, !Player) {
-            if fov.0.contains(&actor.pos) {
+            if fov.sees_actor_at(actor.pos) {
                 positions.push(actor.pos);
             }
         }
@@ -276,12 +259,7 @@ fn ability_input_line(
                 let dist = p_actor.pos.distance(*pos);
                 dist <= max_range && dist >= min_range
             })
-            .filter(|pos| {
-                let line = p_actor.pos.bresenham(*pos);
-                let blocked =
-                    line.iter().skip(1).take(line.len() - 2).any(|pos| tm.is_blocked(*pos));
-                !blocked
-            })
+            .filter(|pos| los::can_hit(p_actor.pos, *pos, |p| tm.is_blocked(p)))
             .next()
             .unwrap_or(p_actor.pos);
         state.cursor_pos = Some(new_cursor);
@@ -306,32 +284,26 @@ fn ability_input_line(
         let color = Color::rgba(1.0, 0.2, 0.3, 0.4);
         c.draw_rect_lines(rect, 3.0, Color::YELLOW, Z_CURSOR);
 
-        let line: Vec<Pos> = p_actor.pos.bresenham(cursor_pos);
-        let mut blocked = false;
-        let mut blocker = None;
-        let mut blocker_pos = None;
+        let blocked = |p| tm.is_blocked(p);
+        let line = los::line_of_fire(p_actor.pos, cursor_pos, blocked);
+        // only the first thing blocking the path can be hit
+        let blocker_pos = los::first_blocker(&line, blocked);
+        let blocker = blocker_pos.and_then(|p| tm.get_actor(p));
         for pos in line.iter().skip(1) {
-            if !blocked {
-                c.draw_rect(pos.to_fpos(TILE_SIZE).rect(TILE_SIZE), color, Z_CURSOR);
-            }
-            if tm.is_blocked(*pos) {
-                // only save actor as blocker if its the first thing blocking the path
-                if !blocked {
-                    blocker = tm.get_actor(*pos);
-                    blocker_pos = Some(*pos);
-                }
-                blocked = true;
+            c.draw_rect(pos.to_fpos(TILE_SIZE).rect(TILE_SIZE), color, Z_CURSOR);
+            if Some(*pos) == blocker_pos {
+                break;
             }
         }
 
         let mut positions = Vec::new();
         for (actor, _draw_pos) in query!(world, Actor, DrawPos, !Player) {
             // TODO check if draw_pos is visible
-            let line = p_actor.pos.bresenham(actor.pos);
-            let blocked =
-                line.iter().skip(1).take(line.len() - 2).any(|pos| tm.is_blocked(*pos));
             let dist = actor.pos.distance(p_actor.pos);
-            if fov.0.contains(&actor.pos) && !blocked && dist >= min_range && dist <= max_range
+            if fov.sees_actor_at(actor.pos)
+                && los::can_hit(p_actor.pos, actor.pos, |p| tm.is_blocked(p))
+                && dist >= min_range
+                && dist <= max_range
             {
                 positions.push(actor.pos);
             }
@@ -370,7 +342,7 @@ fn ability_input_melee_single(c: &mut dyn ContextTrait, world: &mut World) -> Op
     if state.cursor_pos.is_none() {
         let mut positions = Vec::new();
         for (actor, _draw_pos) in query!(world, Actor, DrawPos, !Player) {
-            if fov.0.contains(&actor.pos) {
+            if fov.sees_actor_at(actor.pos) {
                 positions.push(actor.pos);
             }
         }
@@ -401,7 +373,7 @@ fn ability_input_melee_single(c: &mut dyn ContextTrait, world: &mut World) -> Op
 
         let mut positions = Vec::new();
         for (actor, _draw_pos) in query!(world, Actor, DrawPos, !Player) {
-            if fov.0.contains(&actor.pos) {
+            if fov.sees_actor_at(actor.pos) {
                 positions.push(actor.pos);
             }
         }
@@ -417,6 +389,13 @@ fn ability_input_melee_single(c: &mut dyn ContextTrait, world: &mut World) -> Op
         {
             world.defer_closure(exit_ability_state);
             return Some(Action { actor: player, kind: ActionKind::Kick { target } });
+        } else if c.is_pressed(Input::Confirm)
+            && let Some(pos) = state.cursor_pos
+            && pos.distance(p_actor.pos) == 1
+            && tm.tiles.get_opt(pos).is_some_and(|tile| tile.is_door())
+        {
+            world.defer_closure(exit_ability_state);
+            return Some(Action { actor: player, kind: ActionKind::KickDoor { pos } });
         }
     }
 
@@ -0,0 +1,201 @@
+use base::{Pos, pos::IVec, zone};
+use froql::{entity_store::Entity, world::World};
+
+use crate::{
+    animation::{self, BUMP_ATTACK_LENGTH},
+    game::{
+        destruction::damage_terrain,
+        game_logic::{Actor, TileEffect, damage_taken, handle_death, raise_pulse},
+        spatial,
+        sprites::LogicTile,
+        tile_map::TileMap,
+        ui::log_message,
+    },
+};
+
+/// How long a pushed actor takes to fly over one tile
+const FLY_STEP_LENGTH: f32 = 0.06;
+/// Damage for slamming into something, the remaining momentum is added on top
+const IMPACT_DAMAGE: i32 = 1;
+
+/// Something dangerous a pushed actor can end up in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hazard {
+    Fire,
+    Chasm,
+    Water,
+}
+
+enum Obstacle {
+    Wall(Pos),
+    Actor(Entity, Pos),
+}
+
+pub fn hazard_at(world: &World, pos: Pos) -> Option<Hazard> {
+    let tm = world.singleton::<TileMap>();
+    match tm.tiles.get_opt(pos) {
+        Some(LogicTile::Chasm) => return Some(Hazard::Chasm),
+        Some(LogicTile::DeepWater) => return Some(Hazard::Water),
+        _ => {}
+    }
+    let effect = tm.get_effect(pos)?;
+    match *world.get_component::<TileEffect>(effect) {
+        TileEffect::Burning => Some(Hazard::Fire),
+    }
+}
+
+/// Pushes `target` up to `distance` tiles in direction `dir`.
+///
+/// Slamming into a wall or another actor hurts, the actor that was hit
+/// gets pushed further with the remaining momentum.
+/// Landing in a hazard ends the flight. Actors that already died aren't moved.
+///
+/// The animations start at `start_time`, because the animations of whatever caused
+/// the knockback are usually not spawned yet.
+/// Returns the time at which all knockback animations are done.
+pub fn knockback(
+    world: &World,
+    source: Entity,
+    target: Entity,
+    dir: IVec,
+    distance: i32,
+    start_time: f32,
+) -> f32 {
+    zone!();
+    let dir = IVec::new(dir.x.signum(), dir.y.signum());
+    if distance <= 0 || (dir.x == 0 && dir.y == 0) {
+        return start_time;
+    }
+
+    let mut actor = world.get_component_mut::<Actor>(target);
+    if actor.hp.current <= 0 {
+        return start_time;
+    }
+    let from = actor.pos;
+    let mut pos = from;
+    let mut steps = 0;
+    let mut obstacle = None;
+    while steps < distance {
+        let next = pos + dir;
+        let tm = world.singleton::<TileMap>();
+        let flyable =
+            tm.is_walkable(next) || tm.tiles.get_opt(next) == Some(&LogicTile::Chasm);
+        if !flyable {
+            obstacle = Some(Obstacle::Wall(next));
+            break;
+        }
+        if let Some(other) = tm.get_actor(next)
+            && other != target
+        {
+            obstacle = Some(Obstacle::Actor(other, next));
+            break;
+        }
+        pos = next;
+        steps += 1;
+        if hazard_at(world, pos).is_some() {
+            break;
+        }
+    }
+
+    let mut time = start_time;
+    if pos != from {
+        let length = FLY_STEP_LENGTH * steps as f32;
+        let anim = animation::spawn_move_animation_at(world, target, from, pos, time, length);
+        spatial::move_actor(world, target, &mut actor, pos);
+        time += length;
+        if obstacle.is_none() {
+            log_message(world, format!("{} flies back.", actor.name), anim);
+        }
+    }
+
+    let momentum = distance - steps;
+    match obstacle {
+        Some(Obstacle::Wall(wall)) => {
+            let wall_name =
+                world.singleton::<TileMap>().tiles.get_opt(wall).map_or("wall", |t| t.name());
+            let dmg = damage_taken(world, target, IMPACT_DAMAGE + momentum);
+            let hp_change = actor.hp.dmg(dmg);
+            let anim = animation::spawn_bump_attack_animation_at(
+                world, target, target, pos, wall, hp_change, time,
+            );
+            let msg = format!("{} slams into the {wall_name}.", actor.name);
+            log_message(world, msg, anim);
+            raise_pulse(world, target, &actor);
+            handle_death(world, Some(source), target, &actor, anim);
+            damage_terrain(world, source, wall, IMPACT_DAMAGE + momentum, time);
+            time += BUMP_ATTACK_LENGTH;
+        }
+        Some(Obstacle::Actor(other, other_pos)) if other != source => {
+            // both take the hit
+            let dmg = IMPACT_DAMAGE + momentum;
+            let mut other_a = world.get_component_mut::<Actor>(other);
+            let other_alive = other_a.hp.current > 0;
+            let other_dmg = if other_alive { damage_taken(world, other, dmg) } else { 0 };
+            let other_change = other_a.hp.dmg(other_dmg);
+            let anim = animation::spawn_bump_attack_animation_at(
+                world,
+                target,
+                other,
+                pos,
+                other_pos,
+                other_change,
+                time,
+            );
+            let own_dmg = damage_taken(world, target, dmg);
+            let own_change = actor.hp.dmg(own_dmg);
+            animation::spawn_hp_bar_animation_at(
+                world,
+                target,
+                own_change,
+                time + BUMP_ATTACK_LENGTH / 2.,
+                BUMP_ATTACK_LENGTH / 2.,
+            );
+            time += BUMP_ATTACK_LENGTH;
+            let msg = format!("{} crashes into {}.", actor.name, other_a.name);
+            log_message(world, msg, anim);
+            raise_pulse(world, target, &actor);
+            handle_death(world, Some(source), target, &actor, anim);
+            if other_alive {
+                raise_pulse(world, other, &other_a);
+                handle_death(world, Some(source), other, &other_a, anim);
+            }
+            drop(other_a);
+
+            // pass on the remaining momentum
+            time = knockback(world, source, other, dir, momentum, time);
+        }
+        Some(Obstacle::Actor(..)) | None => {}
+    }
+
+    if steps > 0
+        && let Some(hazard) = hazard_at(world, pos)
+    {
+        let was_alive = actor.hp.current > 0;
+        let anim = animation::spawn_empty_animation_at(world, target, time, 0.1);
+        match hazard {
+            Hazard::Fire => {
+                let dmg = if was_alive { damage_taken(world, target, 2) } else { 0 };
+                anim.add(actor.hp.dmg(dmg));
+                log_message(world, format!("{} lands in the flames.", actor.name), *anim);
+            }
+            Hazard::Chasm => {
+                let remaining = actor.hp.current.max(0);
+                anim.add(actor.hp.dmg(remaining));
+                log_message(world, format!("{} falls into the chasm.", actor.name), *anim);
+            }
+            Hazard::Water => {
+                // the splash costs a turn
+                actor.next_turn += 10;
+                let msg = format!("{} splashes into the water.", actor.name);
+                log_message(world, msg, *anim);
+            }
+        }
+        if was_alive {
+            raise_pulse(world, target, &actor);
+            handle_death(world, Some(source), target, &actor, *anim);
+        }
+        time += 0.1;
+    }
+
+    time
+}
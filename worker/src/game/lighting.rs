@@ -0,0 +1,204 @@
+//! How much light falls on every tile of the level.
+//! Light spreads from its sources with the same shadowcasting as the player's sight,
+//! so walls cast shadows.
+
+use base::{Color, Pos, grids::Grid, shadowcasting, zone};
+use froql::{query, world::World};
+
+use crate::game::{
+    dungeon::Dungeon,
+    game_logic::{Actor, Player, TileEffect},
+    sprites::{Decor, Environment},
+    tile_map::{DecorWithPos, TileMap},
+};
+
+/// Tiles need at least this much light to be seen.
+pub const VISIBLE_LIGHT: f32 = 0.2;
+
+/// The torch of the player.
+const PLAYER_LIGHT: LightSource = LightSource { radius: 4, color: Color::rgb(1.0, 0.85, 0.6) };
+const FIRE_LIGHT: LightSource = LightSource { radius: 3, color: Color::rgb(1.0, 0.6, 0.25) };
+
+#[derive(Debug, Clone, Copy)]
+pub struct LightSource {
+    /// Tiles further away than this get no light
+    pub radius: i32,
+    /// Color and brightness at the source
+    pub color: Color,
+}
+
+/// The light on every tile, recomputed each turn.
+/// The alpha of the colors is unused.
+#[derive(Default)]
+pub struct LightMap {
+    pub light: Grid<Color>,
+    /// Depth, [TileMap] revision and player positions the light was computed for
+    computed_for: Option<(i32, u32, Vec<Pos>)>,
+}
+
+impl LightMap {
+    /// Light of the brightest color channel, tiles outside of the map are fully lit.
+    pub fn intensity(&self, pos: Pos) -> f32 {
+        match self.light.get_opt(pos) {
+            Some(c) => c.r.max(c.g).max(c.b),
+            None => 1.0,
+        }
+    }
+
+    pub fn color(&self, pos: Pos) -> Color {
+        self.light.get_opt(pos).copied().unwrap_or(Color::WHITE)
+    }
+
+    /// Lights the tiles around `origin` that the light can reach.
+    /// Brightness falls off linearly until the radius.
+    pub fn add_light(&mut self, tm: &TileMap, origin: Pos, source: LightSource) {
+        let LightSource { radius, color } = source;
+        let in_range = |pos: Pos| {
+            let (dx, dy) = (pos.x - origin.x, pos.y - origin.y);
+            dx * dx + dy * dy <= radius * radius
+        };
+        let light = &mut self.light;
+        shadowcasting::compute_fov(
+            origin,
+            // stopping at the radius keeps the scan small
+            &mut |pos| !in_range(pos) || tm.blocks_vision(pos),
+            &mut |pos| {
+                if !in_range(pos) || light.get_opt(pos).is_none() {
+                    return;
+                }
+                let tile = &mut light[pos];
+                let distance = ((pos.x - origin.x).pow(2) as f32
+                    + (pos.y - origin.y).pow(2) as f32)
+                    .sqrt();
+                let falloff = 1.0 - distance / (radius + 1) as f32;
+                tile.r = (tile.r + color.r * falloff).min(1.0);
+                tile.g = (tile.g + color.g * falloff).min(1.0);
+                tile.b = (tile.b + color.b * falloff).min(1.0);
+            },
+        );
+    }
+}
+
+impl Environment {
+    /// Light that is everywhere on the level, even where no source reaches.
+    pub fn ambient_light(self) -> Color {
+        match self {
+            Environment::Catacomb => Color::rgb(0.06, 0.06, 0.1),
+            Environment::GoblinCaves => Color::rgb(0.1, 0.12, 0.08),
+            Environment::OrcFortress => Color::rgb(0.25, 0.22, 0.18),
+            Environment::BurningForge => Color::rgb(0.4, 0.22, 0.12),
+        }
+    }
+}
+
+impl Decor {
+    pub fn light(self) -> Option<LightSource> {
+        match self {
+            Decor::Brazier => Some(LightSource { radius: 6, color: FIRE_LIGHT.color }),
+            Decor::Lamp => Some(LightSource { radius: 3, color: Color::rgb(0.8, 0.7, 0.45) }),
+            _ => None,
+        }
+    }
+}
+
+/// Recomputes the [LightMap] of the current level.
+/// Fires come and go during a turn, so this is called whenever a turn resolves.
+pub fn update_lighting(world: &World) {
+    zone!();
+    let tm = world.singleton::<TileMap>();
+    let mut map = world.singleton_mut::<LightMap>();
+    map.computed_for = Some(light_key(world, &tm));
+    let ambient = tm.environment.ambient_light();
+    map.light = Grid::new(tm.tiles.width, tm.tiles.height, ambient);
+
+    for (actor,) in query!(world, Actor, _ Player) {
+        map.add_light(&tm, actor.pos, PLAYER_LIGHT);
+    }
+    for (pos, effect) in query!(world, Pos, TileEffect) {
+        match *effect {
+            TileEffect::Burning => map.add_light(&tm, *pos, FIRE_LIGHT),
+        }
+    }
+    for DecorWithPos(pos, decor) in &tm.decor {
+        if let Some(source) = decor.light() {
+            map.add_light(&tm, *pos, source);
+        }
+    }
+}
+
+/// Recomputes the [LightMap] only if the terrain, the level or the player position
+/// changed since it was last computed.
+pub fn refresh_lighting(world: &World) {
+    let stale = {
+        let tm = world.singleton::<TileMap>();
+        let map = world.singleton::<LightMap>();
+        map.computed_for.as_ref() != Some(&light_key(world, &tm))
+    };
+    if stale {
+        update_lighting(world);
+    }
+}
+
+fn light_key(world: &World, tm: &TileMap) -> (i32, u32, Vec<Pos>) {
+    let depth = world.singleton::<Dungeon>().depth;
+    let players = query!(world, Actor, _ Player).map(|(a,)| a.pos).collect();
+    (depth, tm.revision, players)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{game_logic::CreatureType, register_components, sprites::LogicTile};
+
+    #[test]
+    fn light_falls_off_and_casts_shadows() {
+        let mut tm = TileMap::new(12, 5, LogicTile::Floor);
+        tm.enwall();
+        tm.set_tile(Pos::new(6, 1), LogicTile::Wall);
+        tm.set_tile(Pos::new(6, 2), LogicTile::Wall);
+        tm.set_tile(Pos::new(6, 3), LogicTile::Wall);
+        let mut map = LightMap { light: Grid::new(12, 5, Color::BLACK), ..Default::default() };
+        map.add_light(&tm, Pos::new(3, 2), PLAYER_LIGHT);
+
+        let near = map.intensity(Pos::new(3, 2));
+        let far = map.intensity(Pos::new(5, 2));
+        assert!(near > far && far > 0.);
+        assert!(map.intensity(Pos::new(6, 2)) > 0., "walls are lit");
+        assert_eq!(0., map.intensity(Pos::new(7, 2)), "behind the wall");
+
+        // out of range
+        let mut map = LightMap { light: Grid::new(12, 5, Color::BLACK), ..Default::default() };
+        map.add_light(&tm, Pos::new(1, 1), LightSource { radius: 2, ..PLAYER_LIGHT });
+        assert_eq!(0., map.intensity(Pos::new(4, 1)));
+        assert!(map.intensity(Pos::new(3, 1)) > 0.);
+    }
+
+    #[test]
+    fn lighting_is_only_recomputed_when_something_moved() {
+        let mut world = World::new();
+        register_components(&mut world);
+        let mut tm = TileMap::new(12, 5, LogicTile::Floor);
+        tm.enwall();
+        world.singleton_add(tm);
+        world.singleton_add(Dungeon::default());
+        world.singleton_add(LightMap::default());
+        let player =
+            CreatureType::PlayerCharacter.create_deferred(&world, Pos::new(3, 2)).entity;
+        world.process();
+
+        refresh_lighting(&world);
+        let lit = world.singleton::<LightMap>().intensity(Pos::new(3, 2));
+        assert!(lit > 0.);
+
+        // nothing changed, so the map is left alone
+        world.singleton_mut::<LightMap>().light[Pos::new(3, 2)] = Color::BLACK;
+        refresh_lighting(&world);
+        assert_eq!(0., world.singleton::<LightMap>().intensity(Pos::new(3, 2)));
+
+        world.get_component_mut::<Actor>(player).pos = Pos::new(8, 2);
+        refresh_lighting(&world);
+        let map = world.singleton::<LightMap>();
+        assert_eq!(lit, map.intensity(Pos::new(8, 2)));
+        assert!(map.intensity(Pos::new(3, 2)) > 0., "recomputed");
+    }
+}
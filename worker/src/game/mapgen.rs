@@ -1,215 +1,399 @@
-use crate::game::game_logic::CreatureType;
+use crate::game::game_logic::{CreatureType, TileEffect};
 use crate::rand::RandomGenerator;
-use base::Color;
-use base::Pos;
-use base::Rect;
-use base::grids::Grid;
-use froql::component::SYMMETRIC;
-use froql::component::TRANSITIVE;
-use froql::query;
+use base::{Color, Pos};
 use froql::world::World;
+use std::collections::HashSet;
 mod astar_dig;
-use super::sprites::LogicTile;
-use super::sprites::TILE_SIZE;
-use super::tile_map::Room;
-use super::tile_map::TileMap;
-enum Connected {}
+mod bsp;
+mod cave;
+mod drunkards_walk;
+pub mod export;
+mod post_process;
+mod rooms_and_corridors;
+mod validation;
+mod vaults;
+use super::sprites::{Environment, LogicTile};
+use super::tile_map::{MapSpawn, Room, RoomRole, SpawnKind, TileMap};
+use bsp::BspGenerator;
+use cave::CaveGenerator;
+use drunkards_walk::DrunkardsWalkGenerator;
+use rooms_and_corridors::RoomsAndCorridorsGenerator;
+use validation::MapProblem;
 
-pub fn generate_map(seed: u64) -> TileMap {
-    let rand = &mut RandomGenerator::new(seed);
+/// Creates the layout of a level.
+/// The layout is cleaned up afterwards by the passes in [post_process],
+/// so generators don't have to care about connectivity or stairs.
+pub trait MapGenerator {
+    fn generate(&self, rand: &mut RandomGenerator, trace: &mut MapTrace) -> TileMap;
+}
+
+/// Notes a generator leaves about how it built a level, so levels can be inspected
+/// outside of the game.
+#[derive(Debug, Default)]
+pub struct MapTrace {
+    pub generator: &'static str,
+    /// Seed of the attempt that produced the level,
+    /// differs from the requested seed if earlier attempts were broken
+    pub seed: u64,
+    /// Areas of the binary space partitioning, empty for other generators
+    pub partitions: Vec<Partition>,
+    /// Seeds of the broken attempts before this level and what was wrong with them
+    pub rejected: Vec<(u64, MapProblem)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Partition {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    /// How often the map was split to get this area
+    pub level: i32,
+    pub color: Color,
+}
+
+/// How often a broken level is generated again before we fall back to a plain room
+const MAX_ATTEMPTS: usize = 10;
+
+pub fn generate_map(seed: u64, depth: i32) -> TileMap {
+    generate_map_traced(seed, depth, &mut MapTrace::default())
+}
 
-    let world = &mut World::new();
-    world.register_component::<Room>();
-    world.register_component::<Area>();
-    world.register_component::<base::Color>();
-    world.register_component::<ZLevel>();
-    world.register_relation::<Inside>();
-    world.register_relation_flags::<Connected>(SYMMETRIC | TRANSITIVE);
-
-    let width = rand.next_in_range(15, 25) as i32;
-    let height = rand.next_in_range(15, 25) as i32;
-    world
-        .create()
-        .add(Area { x: 0, y: 0, w: width, h: height })
-        .add(Color::WHITE)
-        .add(ZLevel(0));
-
-    // BSP
-    let mut go_on = true;
-    while go_on {
-        go_on = false;
-        for (container, area, z_level) in query!(world, &this, Area, ZLevel, !Inside(_, this))
-        {
-            if area.splitable() {
-                go_on = true;
-                let (a, b) = area.split(rand.next_in_range(4, 6) as i32);
-                let ca = rand.random_color().alpha(0.2);
-                let cb = rand.random_color().alpha(0.2);
-                world
-                    .create_deferred()
-                    .add(a)
-                    .add(ZLevel(z_level.0 + 1))
-                    .add(ca)
-                    .relate_to::<Inside>(*container);
-                world
-                    .create_deferred()
-                    .add(b)
-                    .add(ZLevel(z_level.0 + 1))
-                    .add(cb)
-                    .relate_to::<Inside>(*container);
+/// Like [generate_map], but also fills in what the generator did.
+pub fn generate_map_traced(seed: u64, depth: i32, trace: &mut MapTrace) -> TileMap {
+    let env = environment_for_depth(depth);
+    let mut attempt_seed = seed;
+    let mut rejected = Vec::new();
+    for _ in 0..MAX_ATTEMPTS {
+        let rand = &mut RandomGenerator::new(attempt_seed);
+        *trace = MapTrace { seed: attempt_seed, ..MapTrace::default() };
+
+        let generator = pick_generator(depth, env, rand);
+        let mut tm = generator.generate(rand, trace);
+        tm.environment = env;
+
+        post_process::connect_regions(&mut tm);
+        post_process::place_doors(&mut tm, rand);
+        post_process::place_features(&mut tm, rand);
+        post_process::place_stairs(&mut tm, rand);
+        if validation::validate(&tm).is_err() {
+            validation::repair(&mut tm, rand);
+        }
+
+        match validation::validate(&tm) {
+            Ok(()) => {
+                post_process::assign_room_roles(&mut tm, rand);
+                post_process::place_decor(&mut tm, rand);
+                post_process::decorate_rooms(&mut tm, rand);
+                post_process::place_loot(&mut tm, rand, depth);
+                post_process::place_ambient_fires(&mut tm, rand, ambient_fires(env));
+                post_process::mark_destructible(&mut tm);
+                trace.rejected = rejected;
+                return tm;
+            }
+            Err(problem) => {
+                rejected.push((attempt_seed, problem));
+                attempt_seed = rand.next();
             }
         }
-        world.process();
     }
+    *trace = MapTrace {
+        generator: "fallback",
+        seed: attempt_seed,
+        rejected,
+        ..MapTrace::default()
+    };
+    let mut tm = fallback_map();
+    tm.environment = env;
+    tm
+}
 
-    // for (area, color, z_level) in query!(world, Area, Color, ZLevel) {
-    //     c.draw_rect(area.as_rect().move_by(0., 1000.), *color, z_level.0);
-    // }
-
-    let mut tm = TileMap::new(width, height, LogicTile::Wall);
-
-    for (e, area) in query!(world, &this, Area, !Inside(_, this)) {
-        let room = area.carve(&mut tm.tiles, rand);
-        e.add(room);
-        tm.rooms.push(room);
+/// Every few levels the dungeon changes its look and its inhabitants.
+fn environment_for_depth(depth: i32) -> Environment {
+    match depth {
+        ..=2 => Environment::Catacomb,
+        3..=4 => Environment::GoblinCaves,
+        5..=6 => Environment::OrcFortress,
+        _ => Environment::BurningForge,
     }
+}
 
-    // connect rooms via astar dig
-    'outer: loop {
-        world.process();
-        for (a, room_a) in query!(world, &a, Room(a)) {
-            for (b, room_b) in query!(world, &b, Room(b), !Connected(b, *a), *a != b) {
-                a.relate_to::<Connected>(*b);
-                let Some(path) = astar_dig::astar_orth_dig(&tm, room_a.pos(), room_b.pos())
-                else {
-                    panic!("failed digging")
-                };
-                for pos in path {
-                    tm.tiles[pos] = LogicTile::Floor;
-                }
-                continue 'outer;
-            }
+/// Creatures that live in the environment, spawn groups with others are left out.
+fn monster_pool(env: Environment) -> &'static [CreatureType] {
+    use CreatureType as C;
+    match env {
+        Environment::Catacomb => {
+            &[C::Goblin, C::GoblinArcher, C::GoblinMage, C::GoblinBrute, C::OrcFighter]
+        }
+        Environment::GoblinCaves => {
+            &[C::Goblin, C::GoblinArcher, C::GoblinMage, C::GoblinBrute, C::Troll, C::Ettin]
+        }
+        Environment::OrcFortress => &[
+            C::Goblin,
+            C::GoblinArcher,
+            C::OrcFighter,
+            C::OrcWizard,
+            C::OrcWarchief,
+            C::Troll,
+        ],
+        Environment::BurningForge => {
+            &[C::GoblinMage, C::OrcFighter, C::OrcWizard, C::OrcWarchief, C::Troll, C::Ettin]
         }
-        world.process();
-        break;
     }
+}
 
-    // place up and down stairs in rooms that are far apart
-    for (room_a,) in query!(world, Room) {
-        let room_b = query!(world, Room)
-            .map(|(r,)| r)
-            .max_by_key(|r| r.pos().distance_manhattan(room_a.pos()))
-            .unwrap();
-        tm.up_stairs = room_a.pos();
-        tm.down_stairs = room_b.pos();
-        break;
+/// How many fires burn on a fresh level.
+fn ambient_fires(env: Environment) -> usize {
+    match env {
+        Environment::Catacomb | Environment::GoblinCaves => 0,
+        Environment::OrcFortress => 2,
+        Environment::BurningForge => 8,
     }
+}
 
-    return tm;
+/// A single big room, used if generating a proper level keeps failing.
+fn fallback_map() -> TileMap {
+    let mut tm = TileMap::new(12, 10, LogicTile::Wall);
+    tm.tiles.fill_rect(Pos::new(1, 1), Pos::new(11, 9), LogicTile::Floor);
+    tm.rooms.push(Room { x: 1, y: 1, w: 10, h: 8, role: RoomRole::Plain });
+    tm.up_stairs = Pos::new(1, 1);
+    tm.down_stairs = Pos::new(10, 8);
+    post_process::mark_destructible(&mut tm);
+    tm
 }
 
-pub fn place_enemies(world: &mut World, seed: u64) {
-    let rand = &mut RandomGenerator::new(seed);
-    let tm = world.singleton::<TileMap>();
-    for room in &tm.rooms {
-        for t in 0..room.tile_count() {
-            let pos = room.tile_pos(t);
-            if rand.next_in_range(0, 1000) > 40 || tm.up_stairs == pos {
-                continue;
-            }
-            use CreatureType as S;
-            let creature = rand.pick_random(&[
-                S::Goblin,
-                S::Goblin,
-                S::Goblin,
-                S::OrcFighter,
-                S::GoblinMage,
-                S::GoblinArcher,
-                S::GoblinArcher,
-                S::GoblinBrute,
-                S::GoblinBrute,
-            ]);
-            creature.create_deferred(world, pos);
-        }
+/// No monsters are placed this close to the up stairs
+const SAFE_RADIUS: i32 = 5;
+
+/// A bunch of monsters that are placed together.
+struct SpawnGroup {
+    min_depth: i32,
+    members: &'static [CreatureType],
+}
+
+impl SpawnGroup {
+    fn cost(&self) -> i32 {
+        self.members.iter().map(|c| c.spawn_cost()).sum()
+    }
+
+    fn lives_in(&self, env: Environment) -> bool {
+        self.members.iter().all(|c| monster_pool(env).contains(c))
     }
-    drop(tm); // end borrow
-    world.process();
 }
 
-enum Inside {}
+const SPAWN_GROUPS: &[SpawnGroup] = {
+    use CreatureType as C;
+    &[
+        SpawnGroup { min_depth: 1, members: &[C::Goblin] },
+        SpawnGroup { min_depth: 1, members: &[C::Goblin, C::Goblin, C::Goblin] },
+        SpawnGroup { min_depth: 1, members: &[C::GoblinArcher, C::GoblinArcher, C::Goblin] },
+        SpawnGroup { min_depth: 2, members: &[C::GoblinBrute, C::Goblin, C::Goblin] },
+        SpawnGroup { min_depth: 2, members: &[C::GoblinMage, C::GoblinArcher, C::Goblin] },
+        SpawnGroup { min_depth: 3, members: &[C::OrcFighter, C::OrcFighter] },
+        SpawnGroup { min_depth: 4, members: &[C::OrcWizard, C::OrcFighter, C::Goblin] },
+        SpawnGroup { min_depth: 4, members: &[C::Troll] },
+        SpawnGroup { min_depth: 5, members: &[C::OrcWarchief, C::OrcFighter, C::OrcWizard] },
+        SpawnGroup { min_depth: 6, members: &[C::Ettin] },
+        SpawnGroup { min_depth: 8, members: &[C::Troll, C::Troll] },
+    ]
+};
 
-struct ZLevel(i32);
+/// The total spawn cost of all monsters on a level.
+fn difficulty_budget(depth: i32) -> i32 {
+    4 + depth * 4
+}
 
-#[derive(Debug)]
-struct Area {
-    x: i32,
-    y: i32,
-    w: i32,
-    h: i32,
+/// Levels get bigger the deeper you go.
+fn map_size(
+    depth: i32,
+    rand: &mut RandomGenerator,
+    (min_w, max_w): (i32, i32),
+    (min_h, max_h): (i32, i32),
+) -> (i32, i32) {
+    let growth = ((depth - 1) * 2).clamp(0, 20);
+    let width = rand.next_in_range((min_w + growth) as u64, (max_w + growth) as u64) as i32;
+    let height = rand.next_in_range((min_h + growth) as u64, (max_h + growth) as u64) as i32;
+    (width, height)
 }
 
-impl Area {
-    #[allow(unused)]
-    fn as_rect(&self) -> Rect {
-        Rect {
-            x: self.x as f32 * TILE_SIZE,
-            y: self.y as f32 * TILE_SIZE,
-            w: self.w as f32 * TILE_SIZE,
-            h: self.h as f32 * TILE_SIZE,
+/// The first level is always the classic BSP dungeon,
+/// deeper down the environment decides what the levels look like.
+fn pick_generator(
+    depth: i32,
+    env: Environment,
+    rand: &mut RandomGenerator,
+) -> Box<dyn MapGenerator> {
+    let options: &[u64] = match env {
+        _ if depth <= 1 => &[0],
+        Environment::Catacomb => &[0, 3],
+        Environment::GoblinCaves => &[1, 2],
+        Environment::OrcFortress => &[0, 3],
+        Environment::BurningForge => &[1, 2, 3],
+    };
+    let choice = rand.pick_random(options);
+    match choice {
+        0 => {
+            let (width, height) = map_size(depth, rand, (15, 25), (15, 25));
+            Box::new(BspGenerator { width, height, depth })
+        }
+        1 => {
+            let (width, height) = map_size(depth, rand, (25, 40), (20, 30));
+            Box::new(CaveGenerator { width, height, wall_percent: 45, smoothing_steps: 4 })
+        }
+        2 => {
+            let (width, height) = map_size(depth, rand, (20, 35), (20, 30));
+            Box::new(DrunkardsWalkGenerator {
+                width,
+                height,
+                floor_percent: 35,
+                walk_length: 40,
+            })
+        }
+        _ => {
+            let (width, height) = map_size(depth, rand, (25, 40), (20, 30));
+            Box::new(RoomsAndCorridorsGenerator {
+                width,
+                height,
+                max_rooms: 8,
+                extra_corridors: 2,
+            })
         }
     }
+}
 
-    #[allow(unused)]
-    fn pos(&self) -> Pos {
-        Pos { x: self.x, y: self.y }
-    }
+/// Fills the level with monsters until the difficulty budget for the depth is used up.
+pub fn place_enemies(world: &mut World, seed: u64, depth: i32) {
+    let rand = &mut RandomGenerator::new(seed);
+    let tm = world.singleton::<TileMap>();
+    let env = tm.environment;
+    let is_free = |pos: Pos, taken: &HashSet<Pos>| {
+        tm.is_walkable(pos)
+            && pos.distance(tm.up_stairs) > SAFE_RADIUS
+            && !taken.contains(&pos)
+    };
+    let mut taken = HashSet::new();
+    let mut budget = difficulty_budget(depth);
 
-    #[allow(unused)]
-    fn area(&self) -> i32 {
-        self.w * self.h
+    // vaults bring their own inhabitants, they are paid for by the budget
+    for MapSpawn { pos, kind } in tm.spawns.iter().copied() {
+        if !is_free(pos, &taken) {
+            continue;
+        }
+        match kind {
+            SpawnKind::Monster { creature } => {
+                creature.create_deferred(world, pos);
+                budget -= creature.spawn_cost();
+                taken.insert(pos);
+            }
+            SpawnKind::RandomMonster => {
+                let creature = random_creature(rand, depth, env);
+                creature.create_deferred(world, pos);
+                budget -= creature.spawn_cost();
+                taken.insert(pos);
+            }
+            SpawnKind::Fire if tm.tiles[pos].can_burn() => {
+                world.create_deferred().add(pos).add(TileEffect::Burning);
+            }
+            // fire goes out in water, items and stairs are handled by mapgen
+            SpawnKind::Fire | SpawnKind::Item | SpawnKind::DownStairs => {}
+        }
     }
 
-    fn splitable(&self) -> bool {
-        self.w >= 14 || self.h >= 14
+    // maps without rooms get monsters all over the place
+    let spawn_tiles: Vec<Pos> = if tm.rooms.is_empty() {
+        tm.tiles.coords()
+    } else {
+        tm.rooms
+            .iter()
+            .flat_map(|room| (0..room.tile_count()).map(|t| room.tile_pos(t)))
+            .collect()
+    };
+    let spawn_tiles: Vec<Pos> =
+        spawn_tiles.into_iter().filter(|pos| is_free(*pos, &taken)).collect();
+
+    for _attempt in 0..100 {
+        let groups: Vec<&SpawnGroup> = SPAWN_GROUPS
+            .iter()
+            .filter(|group| group.min_depth <= depth && group.cost() <= budget)
+            .filter(|group| group.lives_in(env))
+            .collect();
+        if groups.is_empty() || spawn_tiles.is_empty() {
+            break;
+        }
+        let group = rand.pick_random(&groups);
+        let leader_pos = rand.pick_random(&spawn_tiles);
+        if !is_free(leader_pos, &taken) {
+            continue;
+        }
+
+        // the group gathers around its leader
+        let mut positions = vec![leader_pos];
+        for radius in 1..=2 {
+            for pos in leader_pos.circle_around(radius) {
+                if positions.len() < group.members.len()
+                    && is_free(pos, &taken)
+                    && !positions.contains(&pos)
+                {
+                    positions.push(pos);
+                }
+            }
+        }
+        for (creature, pos) in group.members.iter().zip(positions) {
+            creature.create_deferred(world, pos);
+            budget -= creature.spawn_cost();
+            taken.insert(pos);
+        }
     }
+    drop(tm); // end borrow
+    world.process();
+}
+
+/// A single monster of the environment that is not too strong for the depth.
+fn random_creature(rand: &mut RandomGenerator, depth: i32, env: Environment) -> CreatureType {
+    let options: Vec<CreatureType> = SPAWN_GROUPS
+        .iter()
+        .filter(|group| group.min_depth <= depth)
+        .flat_map(|group| group.members.iter().copied())
+        .filter(|creature| monster_pool(env).contains(creature))
+        .collect();
+    rand.pick_random(&options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    fn split(&self, ratio: i32) -> (Area, Area) {
-        if self.w > self.h {
-            let w_a = (self.w * ratio) / 10;
-            let w_b = self.w - w_a;
-            let a = Area { x: self.x, y: self.y, w: w_a, h: self.h };
-            // TODO check if off by one
-            let b = Area { x: self.x + w_a, y: self.y, w: w_b, h: self.h };
-            (a, b)
-        } else {
-            let h_a = (self.h * ratio) / 10;
-            let h_b = self.h - h_a;
-            let a = Area { x: self.x, y: self.y, w: self.w, h: h_a };
-            let b = Area { x: self.x, y: self.y + h_a, w: self.w, h: h_b };
-            (a, b)
+    #[test]
+    fn generated_maps_are_playable() {
+        // every environment up to the biggest maps, which are reached at depth 11
+        for seed in 0..2400 {
+            let depth = (seed % 12) as i32 + 1;
+            let mut trace = MapTrace::default();
+            let tm = generate_map_traced(seed, depth, &mut trace);
+            let result = validation::validate(&tm);
+            assert_eq!(Ok(()), result, "seed {seed} at depth {depth}");
+            // the fallback is always playable, so it would hide a broken generator
+            assert_ne!("fallback", trace.generator, "seed {seed} at depth {depth}");
         }
     }
 
-    #[allow(unused)]
-    fn shrink(&self) -> Area {
-        Area { x: self.x + 1, y: self.y + 1, w: self.w - 2, h: self.h - 2 }
+    #[test]
+    fn loot_can_be_picked_up() {
+        for seed in 0..200 {
+            let depth = (seed % 8) as i32 + 1;
+            let tm = generate_map(seed, depth);
+            for loot in &tm.loot {
+                assert!(tm.tiles[loot.pos].can_hold_items(), "seed {seed} at {:?}", loot.pos);
+                assert!(loot.gold >= 5 * depth, "seed {seed}");
+            }
+            let items_left =
+                tm.spawns.iter().any(|spawn| matches!(spawn.kind, SpawnKind::Item));
+            assert!(!items_left, "seed {seed}");
+        }
     }
 
-    fn carve(&self, grid: &mut Grid<LogicTile>, rand: &mut RandomGenerator) -> Room {
-        let min_w = 3.max(self.w - 5);
-        let min_h = 3.max(self.h - 5);
-        let w = rand.next_in_range(min_w as u64, (self.w - 2) as _) as i32;
-        let h = rand.next_in_range(min_h as u64, (self.h - 2) as _) as i32;
-        if min_h > self.h {
-            dbg!(self);
-        }
-        let max_x = self.x + self.w - w;
-        let max_y = self.y + self.h - h;
-        let x = rand.next_in_range(self.x as u64 + 1, max_x as _) as i32;
-        let y = rand.next_in_range(self.y as u64 + 1, max_y as _) as i32;
-        let from = Pos::new(x, y);
-        let to = Pos::new(x + w, y + h);
-        grid.fill_rect(from, to, LogicTile::Floor);
-        Room { x, y, w, h }
+    #[test]
+    fn fallback_map_is_playable() {
+        assert_eq!(Ok(()), validation::validate(&fallback_map()));
     }
 }
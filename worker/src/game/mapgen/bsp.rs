@@ -0,0 +1,204 @@
+use base::Color;
+use base::Pos;
+use base::grids::Grid;
+use froql::component::SYMMETRIC;
+use froql::component::TRANSITIVE;
+use froql::query;
+use froql::world::World;
+
+use super::astar_dig;
+use super::vaults::{Vault, load_vaults, pick_vault};
+use super::{MapGenerator, MapTrace, Partition};
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::TileMap;
+use crate::game::tile_map::{Room, RoomRole};
+use crate::rand::RandomGenerator;
+
+enum Connected {}
+
+/// Where corridors to a leaf of the BSP are dug to
+struct Entrance(Pos);
+
+/// Splits the map into areas via binary space partitioning
+/// and carves a room or stamps a vault into each of them.
+pub struct BspGenerator {
+    pub width: i32,
+    pub height: i32,
+    /// Decides which vaults can show up
+    pub depth: i32,
+}
+
+impl MapGenerator for BspGenerator {
+    fn generate(&self, rand: &mut RandomGenerator, trace: &mut MapTrace) -> TileMap {
+        trace.generator = "bsp";
+        let world = &mut World::new();
+        world.register_component::<Entrance>();
+        world.register_component::<Area>();
+        world.register_component::<base::Color>();
+        world.register_component::<ZLevel>();
+        world.register_relation::<Inside>();
+        world.register_relation_flags::<Connected>(SYMMETRIC | TRANSITIVE);
+
+        let (width, height) = (self.width, self.height);
+        world
+            .create()
+            .add(Area { x: 0, y: 0, w: width, h: height })
+            .add(Color::WHITE)
+            .add(ZLevel(0));
+
+        // BSP
+        let mut go_on = true;
+        while go_on {
+            go_on = false;
+            for (container, area, z_level) in
+                query!(world, &this, Area, ZLevel, !Inside(_, this))
+            {
+                if area.splitable() {
+                    go_on = true;
+                    let (a, b) = area.split(rand.next_in_range(4, 6) as i32);
+                    let ca = rand.random_color().alpha(0.2);
+                    let cb = rand.random_color().alpha(0.2);
+                    world
+                        .create_deferred()
+                        .add(a)
+                        .add(ZLevel(z_level.0 + 1))
+                        .add(ca)
+                        .relate_to::<Inside>(*container);
+                    world
+                        .create_deferred()
+                        .add(b)
+                        .add(ZLevel(z_level.0 + 1))
+                        .add(cb)
+                        .relate_to::<Inside>(*container);
+                }
+            }
+            world.process();
+        }
+
+        for (area, color, z_level) in query!(world, Area, Color, ZLevel) {
+            let Area { x, y, w, h } = *area;
+            trace.partitions.push(Partition { x, y, w, h, level: z_level.0, color: *color });
+        }
+        trace.partitions.sort_by_key(|partition| partition.level);
+
+        let mut tm = TileMap::new(width, height, LogicTile::Wall);
+        let vaults = load_vaults();
+
+        for (e, area) in query!(world, &this, Area, !Inside(_, this)) {
+            if let Some(vault) = pick_vault(&vaults, self.depth, area.w - 2, area.h - 2, rand)
+            {
+                let entrance = area.stamp(&vault, &mut tm, rand);
+                e.add(Entrance(entrance));
+            } else {
+                let room = area.carve(&mut tm.tiles, rand);
+                e.add(Entrance(room.pos()));
+                tm.rooms.push(room);
+            }
+        }
+
+        // connect rooms via astar dig
+        'outer: loop {
+            world.process();
+            for (a, entrance_a) in query!(world, &a, Entrance(a)) {
+                for (b, entrance_b) in
+                    query!(world, &b, Entrance(b), !Connected(b, *a), *a != b)
+                {
+                    a.relate_to::<Connected>(*b);
+                    // missing connections get fixed up by the post processing
+                    let Some(path) =
+                        astar_dig::astar_orth_dig(&tm, entrance_a.0, entrance_b.0)
+                    else {
+                        continue 'outer;
+                    };
+                    for pos in path {
+                        if !tm.is_passable(pos) {
+                            tm.tiles[pos] = LogicTile::Floor;
+                        }
+                    }
+                    continue 'outer;
+                }
+            }
+            world.process();
+            break;
+        }
+
+        tm
+    }
+}
+
+enum Inside {}
+
+struct ZLevel(i32);
+
+#[derive(Debug)]
+struct Area {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Area {
+    #[allow(unused)]
+    fn pos(&self) -> Pos {
+        Pos { x: self.x, y: self.y }
+    }
+
+    #[allow(unused)]
+    fn area(&self) -> i32 {
+        self.w * self.h
+    }
+
+    fn splitable(&self) -> bool {
+        self.w >= 14 || self.h >= 14
+    }
+
+    fn split(&self, ratio: i32) -> (Area, Area) {
+        if self.w > self.h {
+            let w_a = (self.w * ratio) / 10;
+            let w_b = self.w - w_a;
+            let a = Area { x: self.x, y: self.y, w: w_a, h: self.h };
+            // TODO check if off by one
+            let b = Area { x: self.x + w_a, y: self.y, w: w_b, h: self.h };
+            (a, b)
+        } else {
+            let h_a = (self.h * ratio) / 10;
+            let h_b = self.h - h_a;
+            let a = Area { x: self.x, y: self.y, w: self.w, h: h_a };
+            let b = Area { x: self.x, y: self.y + h_a, w: self.w, h: h_b };
+            (a, b)
+        }
+    }
+
+    #[allow(unused)]
+    fn shrink(&self) -> Area {
+        Area { x: self.x + 1, y: self.y + 1, w: self.w - 2, h: self.h - 2 }
+    }
+
+    /// Puts the vault at a random position inside the area, keeping a wall around it.
+    fn stamp(&self, vault: &Vault, tm: &mut TileMap, rand: &mut RandomGenerator) -> Pos {
+        let max_x = self.x + self.w - vault.width();
+        let max_y = self.y + self.h - vault.height();
+        let x = rand.next_in_range(self.x as u64 + 1, max_x as _) as i32;
+        let y = rand.next_in_range(self.y as u64 + 1, max_y as _) as i32;
+        vault.stamp(tm, Pos::new(x, y))
+    }
+
+    fn carve(&self, grid: &mut Grid<LogicTile>, rand: &mut RandomGenerator) -> Room {
+        let min_w = 3.max(self.w - 5);
+        let min_h = 3.max(self.h - 5);
+        let w = rand.next_in_range(min_w as u64, (self.w - 2) as _) as i32;
+        let h = rand.next_in_range(min_h as u64, (self.h - 2) as _) as i32;
+        if min_h > self.h {
+            dbg!(self);
+        }
+        let max_x = self.x + self.w - w;
+        let max_y = self.y + self.h - h;
+        let x = rand.next_in_range(self.x as u64 + 1, max_x as _) as i32;
+        let y = rand.next_in_range(self.y as u64 + 1, max_y as _) as i32;
+        let from = Pos::new(x, y);
+        let to = Pos::new(x + w, y + h);
+        grid.fill_rect(from, to, LogicTile::Floor);
+        Room { x, y, w, h, role: RoomRole::Plain }
+    }
+}
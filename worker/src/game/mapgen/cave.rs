@@ -0,0 +1,60 @@
+use base::{Pos, grids::Grid};
+
+use super::{MapGenerator, MapTrace};
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::TileMap;
+use crate::rand::RandomGenerator;
+
+/// Organic caves grown with a cellular automaton.
+pub struct CaveGenerator {
+    pub width: i32,
+    pub height: i32,
+    /// Chance in percent that a tile starts out as a wall
+    pub wall_percent: u64,
+    pub smoothing_steps: i32,
+}
+
+impl MapGenerator for CaveGenerator {
+    fn generate(&self, rand: &mut RandomGenerator, trace: &mut MapTrace) -> TileMap {
+        trace.generator = "cave";
+        let mut tm = TileMap::new(self.width, self.height, LogicTile::Wall);
+        for (_pos, tile) in tm.tiles.iter_coords_mut() {
+            if rand.next_in_range(0, 100) >= self.wall_percent {
+                *tile = LogicTile::Floor;
+            }
+        }
+        tm.enwall();
+
+        for _ in 0..self.smoothing_steps {
+            let old = tm.tiles.clone();
+            for (pos, tile) in tm.tiles.iter_coords_mut() {
+                let walls = wall_neighbors(&old, pos);
+                if walls >= 5 {
+                    *tile = LogicTile::Wall;
+                } else if walls <= 3 {
+                    *tile = LogicTile::Floor;
+                }
+            }
+            tm.enwall();
+        }
+
+        tm
+    }
+}
+
+/// Counts walls in the 8 neighbors, the outside of the map counts as wall.
+fn wall_neighbors(tiles: &Grid<LogicTile>, pos: Pos) -> i32 {
+    let mut count = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let tile = tiles.get_opt(pos + (dx, dy));
+            if tile.is_none_or(|tile| *tile == LogicTile::Wall) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
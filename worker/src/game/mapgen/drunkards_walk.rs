@@ -0,0 +1,60 @@
+use base::Pos;
+
+use super::{MapGenerator, MapTrace};
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::TileMap;
+use crate::rand::RandomGenerator;
+
+/// Winding tunnels dug by walkers stumbling around randomly.
+pub struct DrunkardsWalkGenerator {
+    pub width: i32,
+    pub height: i32,
+    /// Stop digging once this many percent of the map are floor
+    pub floor_percent: i32,
+    /// How many steps a walker takes before a new one starts
+    pub walk_length: i32,
+}
+
+impl MapGenerator for DrunkardsWalkGenerator {
+    fn generate(&self, rand: &mut RandomGenerator, trace: &mut MapTrace) -> TileMap {
+        trace.generator = "drunkards walk";
+        let mut tm = TileMap::new(self.width, self.height, LogicTile::Wall);
+        let goal = self.width * self.height * self.floor_percent / 100;
+        let center = Pos::new(self.width / 2, self.height / 2);
+        let mut floor_count = 0;
+
+        while floor_count < goal {
+            // new walkers start on already dug floor, so everything stays connected
+            let mut pos = if floor_count == 0 {
+                center
+            } else {
+                let floors: Vec<Pos> = tm
+                    .tiles
+                    .iter_coords()
+                    .filter(|(_, tile)| **tile == LogicTile::Floor)
+                    .map(|(pos, _)| pos)
+                    .collect();
+                rand.pick_random(&floors)
+            };
+
+            for _ in 0..self.walk_length {
+                if tm.tiles[pos] != LogicTile::Floor {
+                    tm.tiles[pos] = LogicTile::Floor;
+                    floor_count += 1;
+                }
+                let (dx, dy) = rand.pick_random(&[(-1, 0), (1, 0), (0, -1), (0, 1)]);
+                // keep a wall around the border
+                let next = pos + (dx, dy);
+                if next.x > 0
+                    && next.x < self.width - 1
+                    && next.y > 0
+                    && next.y < self.height - 1
+                {
+                    pos = next;
+                }
+            }
+        }
+
+        tm
+    }
+}
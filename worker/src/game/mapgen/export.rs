@@ -0,0 +1,368 @@
+//! Generates levels outside of the game and dumps them as text or PNG,
+//! so many seeds can be looked at without starting the game. Used by `src/bin/mapgen.rs`.
+
+use base::{Color, Pos};
+use froql::{query, world::World};
+
+use super::{MapTrace, generate_map_traced, place_enemies, validation};
+use crate::game::game_logic::{Actor, CreatureType};
+use crate::game::register_components;
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::{RoomRole, SpawnKind, TileMap};
+
+/// Size of a tile in the PNG export in pixels
+const CELL: usize = 8;
+
+/// A level as the game would create it, with everything mapgen decided on the way.
+pub struct Inspection {
+    pub seed: u64,
+    pub depth: i32,
+    pub tm: TileMap,
+    pub trace: MapTrace,
+    pub monsters: Vec<(Pos, CreatureType)>,
+    /// Why the level isn't playable, if it isn't
+    pub problem: Option<String>,
+}
+
+pub fn inspect(seed: u64, depth: i32) -> Inspection {
+    let mut trace = MapTrace::default();
+    let tm = generate_map_traced(seed, depth, &mut trace);
+    let problem = validation::validate(&tm).err().map(|problem| format!("{problem:?}"));
+
+    // monsters are placed into a world, so we need a throwaway one
+    let mut world = World::new();
+    register_components(&mut world);
+    world.singleton_add(tm);
+    place_enemies(&mut world, seed, depth);
+    let monsters =
+        query!(world, Actor).map(|(actor,)| (actor.pos, actor.creature_type)).collect();
+    let tm = std::mem::replace(
+        &mut *world.singleton_mut::<TileMap>(),
+        TileMap::new(1, 1, LogicTile::Wall),
+    );
+
+    Inspection { seed, depth, tm, trace, monsters, problem }
+}
+
+impl Inspection {
+    /// One line describing the level.
+    pub fn summary(&self) -> String {
+        let Inspection { seed, depth, tm, trace, monsters, problem } = self;
+        let status = problem.as_deref().unwrap_or("ok");
+        format!(
+            "seed {seed} depth {depth}: {} {:?} {}x{}, {} rooms, {} partitions, {} monsters, {} rejected, {status}",
+            trace.generator,
+            tm.environment,
+            tm.tiles.width,
+            tm.tiles.height,
+            tm.rooms.len(),
+            trace.partitions.len(),
+            monsters.len(),
+            trace.rejected.len(),
+        )
+    }
+
+    /// The level as text, one character per tile.
+    /// Uses the characters of `assets/vaults.txt` where it can.
+    ///
+    /// ```text
+    /// #  wall         .  floor        +  closed door  '  open door
+    /// ~  deep water   :  chasm        ,  rubble       I  pillar
+    /// %  crate        !  barrel       *  explosive barrel
+    /// <  up stairs    >  down stairs  $  gold         ^  fire
+    /// ```
+    /// Monsters use their letter from the vault legend.
+    pub fn ascii(&self) -> String {
+        let tm = &self.tm;
+        let mut chars: Vec<Vec<char>> = (0..tm.tiles.height)
+            .map(|y| {
+                (0..tm.tiles.width).map(|x| tile_char(tm.tiles[Pos::new(x, y)])).collect()
+            })
+            .collect();
+        let mut put = |pos: Pos, c: char| {
+            chars[pos.y as usize][pos.x as usize] = c;
+        };
+        for spawn in &tm.spawns {
+            if let SpawnKind::Fire = spawn.kind {
+                put(spawn.pos, '^');
+            }
+        }
+        for loot in &tm.loot {
+            put(loot.pos, '$');
+        }
+        put(tm.up_stairs, '<');
+        put(tm.down_stairs, '>');
+        for (pos, creature) in &self.monsters {
+            put(*pos, creature_char(*creature));
+        }
+
+        let mut out = String::new();
+        for row in chars {
+            out.extend(row);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The level as a PNG, with rooms, corridors, BSP partitions, stairs and monsters.
+    /// Rooms are outlined in the color of their role.
+    pub fn png(&self) -> Vec<u8> {
+        let tm = &self.tm;
+        let mut image =
+            Image::new(tm.tiles.width as usize * CELL, tm.tiles.height as usize * CELL);
+
+        for pos in tm.tiles.coords() {
+            let tile = tm.tiles[pos];
+            let color = match tile {
+                // floor outside of rooms was dug as a corridor or is part of a cave
+                LogicTile::Floor if tm.rooms.iter().any(|room| room.contains(pos)) => {
+                    [150, 140, 120]
+                }
+                LogicTile::Floor => [95, 90, 80],
+                _ => tile_color(tile),
+            };
+            image.fill_cell(pos, 0, color);
+        }
+        for room in &tm.rooms {
+            let (x, y) = (room.x as usize * CELL, room.y as usize * CELL);
+            let (w, h) = (room.w as usize * CELL, room.h as usize * CELL);
+            let color = match room.role {
+                RoomRole::Plain => [230, 220, 180],
+                RoomRole::TreasureRoom => [255, 210, 0],
+                RoomRole::GuardPost => [220, 60, 60],
+                RoomRole::Shrine => [170, 90, 250],
+            };
+            image.outline(x, y, w, h, color);
+        }
+        // deeper partitions are drawn last, so every split stays visible
+        for partition in &self.trace.partitions {
+            let (x, y) = (partition.x as usize * CELL, partition.y as usize * CELL);
+            let (w, h) = (partition.w as usize * CELL, partition.h as usize * CELL);
+            image.outline(x, y, w, h, rgb(partition.color));
+        }
+        for spawn in &tm.spawns {
+            if let SpawnKind::Fire = spawn.kind {
+                image.fill_cell(spawn.pos, 3, [250, 120, 20]);
+            }
+        }
+        for loot in &tm.loot {
+            image.fill_cell(loot.pos, 3, [240, 200, 40]);
+        }
+        image.fill_cell(tm.up_stairs, 1, [60, 220, 60]);
+        image.fill_cell(tm.down_stairs, 1, [230, 40, 40]);
+        for (pos, creature) in &self.monsters {
+            image.fill_cell(*pos, 2, creature_color(*creature));
+        }
+
+        image.encode_png()
+    }
+}
+
+fn tile_char(tile: LogicTile) -> char {
+    match tile {
+        LogicTile::Empty => ' ',
+        LogicTile::Wall => '#',
+        LogicTile::Floor => '.',
+        LogicTile::Chasm => ':',
+        LogicTile::DeepWater => '~',
+        LogicTile::DoorClosed => '+',
+        LogicTile::DoorOpen => '\'',
+        LogicTile::Rubble => ',',
+        LogicTile::Pillar => 'I',
+        LogicTile::Crate => '%',
+        LogicTile::Barrel => '!',
+        LogicTile::ExplosiveBarrel => '*',
+    }
+}
+
+fn tile_color(tile: LogicTile) -> [u8; 3] {
+    match tile {
+        LogicTile::Empty => [0, 0, 0],
+        LogicTile::Wall => [40, 38, 45],
+        LogicTile::Floor => [150, 140, 120],
+        LogicTile::Chasm => [10, 5, 20],
+        LogicTile::DeepWater => [40, 80, 170],
+        LogicTile::DoorClosed => [130, 80, 30],
+        LogicTile::DoorOpen => [180, 130, 70],
+        LogicTile::Rubble => [115, 105, 90],
+        LogicTile::Pillar => [90, 90, 100],
+        LogicTile::Crate | LogicTile::Barrel => [160, 110, 50],
+        LogicTile::ExplosiveBarrel => [200, 60, 20],
+    }
+}
+
+/// Same letters as in `assets/vaults.txt`.
+fn creature_char(creature: CreatureType) -> char {
+    match creature {
+        CreatureType::PlayerCharacter => '@',
+        CreatureType::Goblin => 'g',
+        CreatureType::GoblinArcher => 'a',
+        CreatureType::GoblinMage => 'm',
+        CreatureType::GoblinBrute => 'b',
+        CreatureType::OrcFighter => 'o',
+        CreatureType::OrcWizard => 'w',
+        CreatureType::OrcWarchief => 'W',
+        CreatureType::Troll => 'T',
+        CreatureType::Ettin => 'E',
+    }
+}
+
+fn creature_color(creature: CreatureType) -> [u8; 3] {
+    match creature {
+        CreatureType::PlayerCharacter => [255, 255, 255],
+        CreatureType::Goblin | CreatureType::GoblinArcher | CreatureType::GoblinMage => {
+            [120, 230, 60]
+        }
+        CreatureType::GoblinBrute => [60, 160, 30],
+        CreatureType::OrcFighter | CreatureType::OrcWizard => [230, 120, 200],
+        CreatureType::OrcWarchief => [200, 40, 160],
+        CreatureType::Troll | CreatureType::Ettin => [250, 240, 80],
+    }
+}
+
+fn rgb(color: Color) -> [u8; 3] {
+    let channel = |c: f32| (c.clamp(0., 1.) * 255.) as u8;
+    [channel(color.r), channel(color.g), channel(color.b)]
+}
+
+/// A plain RGB image that can be written as PNG without any extra dependencies.
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Image { width, height, pixels: vec![[0, 0, 0]; width * height] }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    /// Fills the tile at `pos`, leaving `margin` pixels free on every side.
+    fn fill_cell(&mut self, pos: Pos, margin: usize, color: [u8; 3]) {
+        let (x0, y0) = (pos.x as usize * CELL, pos.y as usize * CELL);
+        for y in y0 + margin..y0 + CELL - margin {
+            for x in x0 + margin..x0 + CELL - margin {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    fn outline(&mut self, x: usize, y: usize, w: usize, h: usize, color: [u8; 3]) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        for dx in 0..w {
+            self.set(x + dx, y, color);
+            self.set(x + dx, y + h - 1, color);
+        }
+        for dy in 0..h {
+            self.set(x, y + dy, color);
+            self.set(x + w - 1, y + dy, color);
+        }
+    }
+
+    /// Uncompressed PNG, the maps are small enough that this doesn't matter.
+    fn encode_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for row in self.pixels.chunks(self.width.max(1)) {
+            raw.push(0); // no filter
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut ihdr = Vec::new();
+        ihdr.extend((self.width as u32).to_be_bytes());
+        ihdr.extend((self.height as u32).to_be_bytes());
+        // 8 bit depth, truecolor, default compression, filter and no interlacing
+        ihdr.extend([8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(kind.iter().chain(data));
+    out.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream made of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        out.push(last as u8);
+        let len = block.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(0xae426082, crc32(b"IEND"));
+        assert_eq!(0x11e60398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn exports_match_the_map() {
+        let inspection = inspect(7, 1);
+        let (w, h) = (inspection.tm.tiles.width, inspection.tm.tiles.height);
+        let ascii = inspection.ascii();
+        assert_eq!(h as usize, ascii.lines().count());
+        assert!(ascii.lines().all(|line| line.chars().count() == w as usize));
+        assert!(ascii.contains('<') && ascii.contains('>'));
+        assert_eq!("bsp", inspection.trace.generator);
+        assert!(!inspection.trace.partitions.is_empty());
+
+        let png = inspection.png();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(
+            (w as usize * CELL) as u32,
+            u32::from_be_bytes(png[16..20].try_into().unwrap())
+        );
+        assert!(png.ends_with(&0xae426082u32.to_be_bytes()));
+    }
+}
@@ -0,0 +1,413 @@
+//! Shared passes that turn the raw layout of a [super::MapGenerator] into a playable level.
+
+use std::collections::VecDeque;
+
+use base::{Pos, grids::Grid};
+
+use super::astar_dig::astar_orth_dig;
+use crate::game::sprites::{Decor, LogicTile};
+use crate::game::tile_map::{Loot, MapSpawn, Room, RoomRole, SpawnKind, TileMap};
+use crate::rand::RandomGenerator;
+
+/// Connects every walkable region to the biggest one by digging tunnels.
+/// Regions that can't be connected are filled in.
+pub fn connect_regions(tm: &mut TileMap) {
+    let mut regions = walkable_regions(tm);
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    let Some((main, others)) = regions.split_first() else { return };
+
+    for region in others {
+        let start = region[0];
+        let goal = *main.iter().min_by_key(|pos| pos.distance_manhattan(start)).unwrap();
+        if let Some(path) = astar_orth_dig(tm, start, goal) {
+            for pos in path {
+                if !tm.is_passable(pos) {
+                    tm.tiles[pos] = LogicTile::Floor;
+                }
+            }
+        } else {
+            for pos in region {
+                tm.tiles[*pos] = LogicTile::Wall;
+            }
+        }
+    }
+}
+
+/// Puts doors into the narrow openings around rooms.
+pub fn place_doors(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    let rooms = tm.rooms.clone();
+    for room in rooms {
+        let (x0, y0, x1, y1) = (room.x - 1, room.y - 1, room.x + room.w, room.y + room.h);
+        let outline = (x0 + 1..x1)
+            .flat_map(|x| [Pos::new(x, y0), Pos::new(x, y1)])
+            .chain((y0 + 1..y1).flat_map(|y| [Pos::new(x0, y), Pos::new(x1, y)]));
+        for pos in outline {
+            if tm.tiles.get_opt(pos) != Some(&LogicTile::Floor) || !is_doorway(tm, pos) {
+                continue;
+            }
+            let next_to_door = pos.neighbors(&tm.tiles).any(|next| tm.tiles[next].is_door());
+            if next_to_door {
+                continue;
+            }
+            tm.tiles[pos] = if rand.next_in_range(0, 3) == 0 {
+                LogicTile::DoorOpen
+            } else {
+                LogicTile::DoorClosed
+            };
+        }
+    }
+}
+
+/// A passage that is exactly one tile wide.
+fn is_doorway(tm: &TileMap, pos: Pos) -> bool {
+    let blocked = |dx: i32, dy: i32| !tm.is_passable(pos + (dx, dy));
+    let horizontal = blocked(0, -1) && blocked(0, 1) && !blocked(-1, 0) && !blocked(1, 0);
+    let vertical = blocked(-1, 0) && blocked(1, 0) && !blocked(0, -1) && !blocked(0, 1);
+    horizontal || vertical
+}
+
+/// Adds pillars and props to rooms and scatters rubble, pools of water and pits over the level.
+/// Nothing placed here can cut off a part of the level.
+pub fn place_features(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    // pillars in the corners of big rooms
+    let rooms = tm.rooms.clone();
+    for room in rooms {
+        if room.w < 5 || room.h < 5 || rand.next_in_range(0, 3) != 0 {
+            continue;
+        }
+        let (left, right) = (room.x + 1, room.x + room.w - 2);
+        let (top, bottom) = (room.y + 1, room.y + room.h - 2);
+        for (x, y) in [(left, top), (right, top), (left, bottom), (right, bottom)] {
+            let pos = Pos::new(x, y);
+            if is_open(tm, pos) {
+                tm.tiles[pos] = LogicTile::Pillar;
+            }
+        }
+    }
+
+    // crates and barrels in the corners of rooms
+    let rooms = tm.rooms.clone();
+    for room in rooms {
+        let corners = [
+            Pos::new(room.x, room.y),
+            Pos::new(room.x + room.w - 1, room.y),
+            Pos::new(room.x, room.y + room.h - 1),
+            Pos::new(room.x + room.w - 1, room.y + room.h - 1),
+        ];
+        for pos in corners {
+            if room.w < 3 || room.h < 3 || rand.next_in_range(0, 4) != 0 {
+                continue;
+            }
+            // only where nothing from outside the room leads in
+            let closed_off = pos.neighbors(&tm.tiles).all(|next| {
+                let inside = next.x >= room.x
+                    && next.x < room.x + room.w
+                    && next.y >= room.y
+                    && next.y < room.y + room.h;
+                inside || !tm.is_passable(next)
+            });
+            if tm.tiles[pos] != LogicTile::Floor || room.pos() == pos || !closed_off {
+                continue;
+            }
+            tm.tiles[pos] = match rand.next_in_range(0, 10) {
+                0..5 => LogicTile::Crate,
+                5..8 => LogicTile::Barrel,
+                _ => LogicTile::ExplosiveBarrel,
+            };
+        }
+    }
+
+    // pools of water
+    let floors: Vec<Pos> = tm
+        .tiles
+        .coords()
+        .into_iter()
+        .filter(|pos| tm.tiles[*pos] == LogicTile::Floor)
+        .collect();
+    if floors.is_empty() {
+        return;
+    }
+    for _ in 0..rand.next_in_range(0, 3) {
+        let center = rand.pick_random(&floors);
+        let radius = rand.next_in_range(1, 3) as i32;
+        for pos in tm.tiles.coords() {
+            if pos.distance_manhattan(center) <= radius && tm.tiles[pos] == LogicTile::Floor {
+                tm.tiles[pos] = LogicTile::DeepWater;
+            }
+        }
+    }
+
+    // single pits in open areas
+    for _ in 0..rand.next_in_range(0, 4) {
+        let pos = rand.pick_random(&floors);
+        if is_open(tm, pos) {
+            tm.tiles[pos] = LogicTile::Chasm;
+        }
+    }
+
+    for pos in floors {
+        if tm.tiles[pos] == LogicTile::Floor && rand.next_in_range(0, 100) < 2 {
+            tm.tiles[pos] = LogicTile::Rubble;
+        }
+    }
+}
+
+/// Gives breakable terrain its integrity.
+/// The border of the map stays unbreakable, so nobody can dig their way out of the level.
+pub fn mark_destructible(tm: &mut TileMap) {
+    let (w, h) = (tm.tiles.width, tm.tiles.height);
+    for pos in tm.tiles.coords() {
+        let border = pos.x == 0 || pos.y == 0 || pos.x == w - 1 || pos.y == h - 1;
+        tm.integrity[pos] = if border { 0 } else { tm.tiles[pos].base_integrity() };
+    }
+}
+
+/// A floor tile that is completely surrounded by walkable tiles.
+/// Blocking it never disconnects anything, as the tiles around it are still connected.
+fn is_open(tm: &TileMap, pos: Pos) -> bool {
+    let room_center = tm.rooms.iter().any(|room| room.pos() == pos);
+    tm.tiles.get_opt(pos) == Some(&LogicTile::Floor)
+        && !room_center
+        && pos.neighbors(&tm.tiles).count() == 8
+        && pos.neighbors(&tm.tiles).all(|next| tm.is_walkable(next))
+}
+
+/// Puts the up stairs in the first room (or on a random floor tile if there are no rooms)
+/// and the down stairs as far away from it as possible,
+/// preferably where a vault wants them.
+pub fn place_stairs(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    let floors: Vec<Pos> = tm
+        .tiles
+        .coords()
+        .into_iter()
+        .filter(|pos| tm.is_walkable(*pos) && !tm.tiles[*pos].is_door())
+        .collect();
+    if floors.is_empty() {
+        return;
+    }
+    let up = match tm.rooms.first() {
+        Some(room) if tm.is_walkable(room.pos()) => room.pos(),
+        _ => rand.pick_random(&floors),
+    };
+
+    // on maps with rooms the stairs go into the center of a room
+    let distances = walk_distances(tm, up);
+    let farthest = |candidates: &[Pos]| {
+        candidates
+            .iter()
+            .copied()
+            .filter(|pos| *pos != up && distances[*pos] != i32::MAX)
+            .max_by_key(|pos| distances[*pos])
+    };
+    // vaults can ask for the stairs to be in them
+    let vault_stairs: Vec<Pos> = tm
+        .spawns
+        .iter()
+        .filter(|spawn| matches!(spawn.kind, SpawnKind::DownStairs))
+        .map(|spawn| spawn.pos)
+        .collect();
+    let room_centers: Vec<Pos> = tm.rooms.iter().map(|room| room.pos()).collect();
+    let down = farthest(&vault_stairs)
+        .or_else(|| farthest(&room_centers))
+        .or_else(|| farthest(&floors))
+        .unwrap_or(up);
+
+    tm.up_stairs = up;
+    tm.down_stairs = down;
+}
+
+/// Sprinkles the decor of the environment over the floor.
+pub fn place_decor(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    let pool = tm.environment.decor();
+    for pos in tm.tiles.coords() {
+        if tm.tiles[pos] != LogicTile::Floor || pos == tm.up_stairs || pos == tm.down_stairs {
+            continue;
+        }
+        if rand.next_in_range(0, 100) < 2 {
+            let decor = rand.pick_random(pool);
+            tm.add_decor(pos, decor);
+        }
+    }
+}
+
+/// Gives some rooms a purpose, the rooms with the stairs are left alone.
+/// A treasure room is guarded by the room closest to it.
+pub fn assign_room_roles(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    let candidates: Vec<usize> = (0..tm.rooms.len())
+        .filter(|i| {
+            let room = &tm.rooms[*i];
+            !room.contains(tm.up_stairs) && !room.contains(tm.down_stairs)
+        })
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+
+    if rand.next_in_range(0, 2) == 0 {
+        let treasure = rand.pick_random(&candidates);
+        tm.rooms[treasure].role = RoomRole::TreasureRoom;
+        let center = tm.rooms[treasure].pos();
+        let guards = candidates
+            .iter()
+            .copied()
+            .filter(|i| *i != treasure)
+            .min_by_key(|i| tm.rooms[*i].pos().distance(center));
+        if let Some(guards) = guards {
+            tm.rooms[guards].role = RoomRole::GuardPost;
+        }
+    }
+    for i in candidates {
+        if tm.rooms[i].role == RoomRole::Plain && rand.next_in_range(0, 8) == 0 {
+            tm.rooms[i].role = RoomRole::Shrine;
+        }
+    }
+}
+
+/// Decor that fits the role of each room.
+pub fn decorate_rooms(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    let rooms = tm.rooms.clone();
+    for room in rooms {
+        let floors = free_floors(tm, &room);
+        if floors.is_empty() {
+            continue;
+        }
+        let (pool, count): (&[Decor], u64) = match room.role {
+            RoomRole::Plain => continue,
+            // the remains of those who came before
+            RoomRole::TreasureRoom => {
+                (&[Decor::Bones1, Decor::Bones2], rand.next_in_range(1, 4))
+            }
+            RoomRole::GuardPost => {
+                // the guards keep a fire going
+                let pos = rand.pick_random(&floors);
+                tm.add_decor(pos, Decor::Brazier);
+                (
+                    &[Decor::BloodRed1, Decor::BloodRed2, Decor::LogPile],
+                    rand.next_in_range(2, 5),
+                )
+            }
+            RoomRole::Shrine => {
+                let center = room.pos();
+                if floors.contains(&center) {
+                    tm.add_decor(center, Decor::Pentagram);
+                }
+                (&[Decor::Lamp], rand.next_in_range(2, 5))
+            }
+        };
+        for _ in 0..count {
+            let pos = rand.pick_random(&floors);
+            if !tm.decor.iter().any(|decor| decor.0 == pos) {
+                let decor = rand.pick_random(pool);
+                tm.add_decor(pos, decor);
+            }
+        }
+    }
+}
+
+/// Turns the items of vaults into gold and puts more of it into rooms,
+/// treasure rooms get a whole cache. Loot gets more valuable the deeper you go.
+pub fn place_loot(tm: &mut TileMap, rand: &mut RandomGenerator, depth: i32) {
+    let value = |rand: &mut RandomGenerator| rand.next_in_range(5, 16) as i32 * depth;
+    let mut piles = Vec::new();
+
+    for spawn in &tm.spawns {
+        if let SpawnKind::Item = spawn.kind {
+            piles.push(Loot { pos: spawn.pos, gold: value(rand) });
+        }
+    }
+    tm.spawns.retain(|spawn| !matches!(spawn.kind, SpawnKind::Item));
+
+    for room in &tm.rooms {
+        let floors = free_floors(tm, room);
+        if floors.is_empty() {
+            continue;
+        }
+        let (count, multiplier) = match room.role {
+            RoomRole::TreasureRoom => (rand.next_in_range(3, 6), 2),
+            RoomRole::GuardPost => (1, 1),
+            RoomRole::Shrine => (rand.next_in_range(0, 2), 1),
+            RoomRole::Plain => ((rand.next_in_range(0, 4) == 0) as u64, 1),
+        };
+        for _ in 0..count {
+            let pos = rand.pick_random(&floors);
+            piles.push(Loot { pos, gold: value(rand) * multiplier });
+        }
+    }
+    // maps without rooms get their loot scattered all over the place
+    if tm.rooms.is_empty() {
+        let floors: Vec<Pos> = tm
+            .tiles
+            .coords()
+            .into_iter()
+            .filter(|pos| tm.tiles[*pos] == LogicTile::Floor)
+            .collect();
+        if !floors.is_empty() {
+            for _ in 0..rand.next_in_range(2, 5) {
+                let pos = rand.pick_random(&floors);
+                piles.push(Loot { pos, gold: value(rand) });
+            }
+        }
+    }
+
+    for pile in piles {
+        // gold sinks in deep water
+        let stairs = pile.pos == tm.up_stairs || pile.pos == tm.down_stairs;
+        if stairs || !tm.tiles[pile.pos].can_hold_items() {
+            continue;
+        }
+        match tm.loot.iter_mut().find(|loot| loot.pos == pile.pos) {
+            Some(loot) => loot.gold += pile.gold,
+            None => tm.loot.push(pile),
+        }
+    }
+}
+
+/// Floor tiles in the room without stairs.
+fn free_floors(tm: &TileMap, room: &Room) -> Vec<Pos> {
+    (0..room.tile_count())
+        .map(|t| room.tile_pos(t))
+        .filter(|pos| tm.tiles.get_opt(*pos) == Some(&LogicTile::Floor))
+        .filter(|pos| *pos != tm.up_stairs && *pos != tm.down_stairs)
+        .collect()
+}
+
+/// Fires that are already burning when the player arrives.
+pub fn place_ambient_fires(tm: &mut TileMap, rand: &mut RandomGenerator, count: usize) {
+    let candidates: Vec<Pos> = tm
+        .tiles
+        .coords()
+        .into_iter()
+        .filter(|pos| {
+            tm.tiles[*pos].can_burn() && *pos != tm.up_stairs && *pos != tm.down_stairs
+        })
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    for _ in 0..count {
+        let pos = rand.pick_random(&candidates);
+        tm.spawns.push(MapSpawn { pos, kind: SpawnKind::Fire });
+    }
+}
+
+/// All groups of passable tiles that are connected orthogonally.
+pub fn walkable_regions(tm: &TileMap) -> Vec<Vec<Pos>> {
+    tm.tiles.components(|_, tile| tile.is_passable()).regions()
+}
+
+/// Number of steps needed to walk to every tile from `start`.
+/// Unreachable tiles are `i32::MAX`.
+pub fn walk_distances(tm: &TileMap, start: Pos) -> Grid<i32> {
+    let mut distances = Grid::new(tm.tiles.width, tm.tiles.height, i32::MAX);
+    let mut todo = VecDeque::from([start]);
+    distances[start] = 0;
+    while let Some(pos) = todo.pop_front() {
+        for next in pos.neighbors(&tm.tiles) {
+            if distances[next] == i32::MAX && tm.is_passable(next) {
+                distances[next] = distances[pos] + 1;
+                todo.push_back(next);
+            }
+        }
+    }
+    distances
+}
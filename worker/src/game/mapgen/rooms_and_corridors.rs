@@ -0,0 +1,72 @@
+use base::Pos;
+
+use super::{MapGenerator, MapTrace};
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::{Room, RoomRole, TileMap};
+use crate::rand::RandomGenerator;
+
+/// Randomly scattered rooms, chained together by corridors.
+/// A few extra corridors create loops so there is more than one way around.
+pub struct RoomsAndCorridorsGenerator {
+    pub width: i32,
+    pub height: i32,
+    pub max_rooms: i32,
+    pub extra_corridors: i32,
+}
+
+impl MapGenerator for RoomsAndCorridorsGenerator {
+    fn generate(&self, rand: &mut RandomGenerator, trace: &mut MapTrace) -> TileMap {
+        trace.generator = "rooms and corridors";
+        let mut tm = TileMap::new(self.width, self.height, LogicTile::Wall);
+
+        for _ in 0..(self.max_rooms * 5) {
+            if tm.rooms.len() as i32 >= self.max_rooms {
+                break;
+            }
+            let w = rand.next_in_range(3, 8) as i32;
+            let h = rand.next_in_range(3, 7) as i32;
+            let x = rand.next_in_range(1, (self.width - w - 1) as u64) as i32;
+            let y = rand.next_in_range(1, (self.height - h - 1) as u64) as i32;
+            let room = Room { x, y, w, h, role: RoomRole::Plain };
+            if tm.rooms.iter().any(|other| overlaps(&room, other)) {
+                continue;
+            }
+            tm.tiles.fill_rect(Pos::new(x, y), Pos::new(x + w, y + h), LogicTile::Floor);
+            tm.rooms.push(room);
+        }
+
+        for i in 1..tm.rooms.len() {
+            let from = tm.rooms[i - 1].pos();
+            let to = tm.rooms[i].pos();
+            dig_corridor(&mut tm, from, to, rand);
+        }
+
+        // loops
+        if tm.rooms.len() > 2 {
+            for _ in 0..self.extra_corridors {
+                let from = rand.pick_random(&tm.rooms).pos();
+                let to = rand.pick_random(&tm.rooms).pos();
+                dig_corridor(&mut tm, from, to, rand);
+            }
+        }
+
+        tm
+    }
+}
+
+/// True if the rooms overlap or touch.
+fn overlaps(a: &Room, b: &Room) -> bool {
+    a.x <= b.x + b.w && b.x <= a.x + a.w && a.y <= b.y + b.h && b.y <= a.y + a.h
+}
+
+/// Digs an L-shaped corridor, randomly going horizontal or vertical first.
+fn dig_corridor(tm: &mut TileMap, from: Pos, to: Pos, rand: &mut RandomGenerator) {
+    let corner = if rand.next_in_range(0, 2) == 0 {
+        Pos::new(to.x, from.y)
+    } else {
+        Pos::new(from.x, to.y)
+    };
+    for pos in from.bresenham(corner).into_iter().chain(corner.bresenham(to)) {
+        tm.tiles[pos] = LogicTile::Floor;
+    }
+}
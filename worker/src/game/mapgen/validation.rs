@@ -0,0 +1,60 @@
+use super::post_process::{connect_regions, place_stairs, walk_distances, walkable_regions};
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::TileMap;
+use crate::rand::RandomGenerator;
+
+/// Something that makes a generated level unplayable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapProblem {
+    NoFloor,
+    StairsBlocked,
+    StairsOnSameTile,
+    StairsUnreachable,
+    /// Not every walkable tile can be reached from the up stairs
+    Disconnected,
+    /// The center of a room is not walkable
+    RoomCenterBlocked,
+}
+
+/// Checks that the level can be played from the up stairs to the down stairs.
+pub fn validate(tm: &TileMap) -> Result<(), MapProblem> {
+    let regions = walkable_regions(tm);
+    if regions.is_empty() {
+        return Err(MapProblem::NoFloor);
+    }
+    if !tm.is_walkable(tm.up_stairs) || !tm.is_walkable(tm.down_stairs) {
+        return Err(MapProblem::StairsBlocked);
+    }
+    if tm.up_stairs == tm.down_stairs {
+        return Err(MapProblem::StairsOnSameTile);
+    }
+    if walk_distances(tm, tm.up_stairs)[tm.down_stairs] == i32::MAX {
+        return Err(MapProblem::StairsUnreachable);
+    }
+    if regions.len() > 1 {
+        return Err(MapProblem::Disconnected);
+    }
+    if tm.rooms.iter().any(|room| !tm.is_walkable(room.pos())) {
+        return Err(MapProblem::RoomCenterBlocked);
+    }
+    Ok(())
+}
+
+/// Tries to fix the problems found by [validate] in place.
+/// The result still needs to be validated again.
+pub fn repair(tm: &mut TileMap, rand: &mut RandomGenerator) {
+    let centers: Vec<_> = tm.rooms.iter().map(|room| room.pos()).collect();
+    for center in centers {
+        if tm.tiles.is_valid(center) && !tm.is_walkable(center) {
+            tm.tiles[center] = LogicTile::Floor;
+        }
+    }
+    connect_regions(tm);
+
+    if !tm.is_walkable(tm.up_stairs)
+        || !tm.is_walkable(tm.down_stairs)
+        || tm.up_stairs == tm.down_stairs
+    {
+        place_stairs(tm, rand);
+    }
+}
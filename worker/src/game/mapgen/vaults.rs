@@ -0,0 +1,215 @@
+use base::Pos;
+
+use crate::game::game_logic::CreatureType;
+use crate::game::sprites::LogicTile;
+use crate::game::tile_map::{MapSpawn, SpawnKind, TileMap};
+use crate::rand::RandomGenerator;
+
+const VAULTS: &str = include_str!("../../../../assets/vaults.txt");
+
+/// A hand made set piece, see `assets/vaults.txt` for the format.
+#[derive(Debug, Clone)]
+pub struct Vault {
+    pub name: String,
+    pub min_depth: i32,
+    pub max_depth: i32,
+    /// A suitable room becomes this vault with a chance of 1 in `rarity`
+    pub rarity: u64,
+    /// Rows of the template, all rows have the same length
+    pub cells: Vec<Vec<char>>,
+}
+
+impl Vault {
+    pub fn width(&self) -> i32 {
+        self.cells.first().map_or(0, |row| row.len() as i32)
+    }
+
+    pub fn height(&self) -> i32 {
+        self.cells.len() as i32
+    }
+
+    /// Rotates the template clockwise by 90° `quarter_turns` times,
+    /// mirrors it horizontally afterwards if requested.
+    pub fn transformed(&self, quarter_turns: i32, mirror: bool) -> Vault {
+        let mut cells = self.cells.clone();
+        for _ in 0..quarter_turns.rem_euclid(4) {
+            let h = cells.len();
+            let w = cells.first().map_or(0, |row| row.len());
+            cells = (0..w).map(|x| (0..h).rev().map(|y| cells[y][x]).collect()).collect();
+        }
+        if mirror {
+            for row in &mut cells {
+                row.reverse();
+            }
+        }
+        Vault { cells, ..self.clone() }
+    }
+
+    /// Writes the vault into the map with its top left corner at `origin`.
+    /// Returns the entrance that corridors should be dug to.
+    pub fn stamp(&self, tm: &mut TileMap, origin: Pos) -> Pos {
+        let mut doors = Vec::new();
+        let mut floors = Vec::new();
+        for (y, row) in self.cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let pos = Pos::new(origin.x + x as i32, origin.y + y as i32);
+                let Some((tile, spawn)) = legend(*cell) else { continue };
+                tm.tiles[pos] = tile;
+                if let Some(kind) = spawn {
+                    tm.spawns.push(MapSpawn { pos, kind });
+                }
+                match *cell {
+                    '+' => doors.push(pos),
+                    _ if tile == LogicTile::Floor => floors.push(pos),
+                    _ => {}
+                }
+            }
+        }
+        let center = Pos::new(origin.x + self.width() / 2, origin.y + self.height() / 2);
+        doors
+            .first()
+            .copied()
+            .or_else(|| floors.iter().copied().min_by_key(|pos| pos.distance(center)))
+            .unwrap_or(center)
+    }
+}
+
+/// What a template character turns into.
+/// None for characters that leave the map untouched.
+fn legend(c: char) -> Option<(LogicTile, Option<SpawnKind>)> {
+    use CreatureType as C;
+    let monster = |creature| Some((LogicTile::Floor, Some(SpawnKind::Monster { creature })));
+    match c {
+        ' ' => None,
+        '#' => Some((LogicTile::Wall, None)),
+        '.' => Some((LogicTile::Floor, None)),
+        '+' => Some((LogicTile::DoorClosed, None)),
+        '$' => Some((LogicTile::Floor, Some(SpawnKind::Item))),
+        '^' => Some((LogicTile::Floor, Some(SpawnKind::Fire))),
+        '>' => Some((LogicTile::Floor, Some(SpawnKind::DownStairs))),
+        'M' => Some((LogicTile::Floor, Some(SpawnKind::RandomMonster))),
+        'g' => monster(C::Goblin),
+        'a' => monster(C::GoblinArcher),
+        'm' => monster(C::GoblinMage),
+        'b' => monster(C::GoblinBrute),
+        'o' => monster(C::OrcFighter),
+        'w' => monster(C::OrcWizard),
+        'W' => monster(C::OrcWarchief),
+        'T' => monster(C::Troll),
+        'E' => monster(C::Ettin),
+        _ => None,
+    }
+}
+
+pub fn load_vaults() -> Vec<Vault> {
+    parse_vaults(VAULTS).expect("assets/vaults.txt is broken")
+}
+
+pub fn parse_vaults(text: &str) -> Result<Vec<Vault>, String> {
+    let mut vaults = Vec::new();
+    let mut current: Option<Vault> = None;
+
+    for (nr, line) in text.lines().enumerate() {
+        let nr = nr + 1;
+        if line.starts_with(';') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            vaults.extend(current.take());
+            continue;
+        }
+
+        let vault = current.get_or_insert_with(|| Vault {
+            name: String::new(),
+            min_depth: 1,
+            max_depth: i32::MAX,
+            rarity: 1,
+            cells: Vec::new(),
+        });
+        if let Some(name) = line.strip_prefix("name:") {
+            vault.name = name.trim().to_string();
+        } else if let Some(depth) = line.strip_prefix("depth:") {
+            let (min, max) = depth
+                .split_once('-')
+                .ok_or_else(|| format!("line {nr}: depth needs min-max"))?;
+            vault.min_depth = min.trim().parse().map_err(|e| format!("line {nr}: {e}"))?;
+            vault.max_depth = max.trim().parse().map_err(|e| format!("line {nr}: {e}"))?;
+        } else if let Some(rarity) = line.strip_prefix("rarity:") {
+            vault.rarity = rarity.trim().parse().map_err(|e| format!("line {nr}: {e}"))?;
+        } else {
+            if let Some(c) = line.chars().find(|c| *c != ' ' && legend(*c).is_none()) {
+                return Err(format!("line {nr}: unknown character '{c}'"));
+            }
+            vault.cells.push(line.chars().collect());
+        }
+    }
+    vaults.extend(current);
+
+    for vault in &mut vaults {
+        if vault.cells.is_empty() {
+            return Err(format!("vault '{}' has no template", vault.name));
+        }
+        // pad rows so the template is rectangular
+        let width = vault.cells.iter().map(|row| row.len()).max().unwrap_or(0);
+        for row in &mut vault.cells {
+            row.resize(width, ' ');
+        }
+    }
+    Ok(vaults)
+}
+
+/// Picks a vault for the given depth that fits into a `w` x `h` area.
+/// Every vault only gets a chance of 1 in its rarity.
+pub fn pick_vault(
+    vaults: &[Vault],
+    depth: i32,
+    w: i32,
+    h: i32,
+    rand: &mut RandomGenerator,
+) -> Option<Vault> {
+    let quarter_turns = rand.next_in_range(0, 4) as i32;
+    let mirror = rand.next_in_range(0, 2) == 0;
+    let candidates: Vec<Vault> = vaults
+        .iter()
+        .filter(|vault| vault.min_depth <= depth && depth <= vault.max_depth)
+        .map(|vault| vault.transformed(quarter_turns, mirror))
+        .filter(|vault| vault.width() <= w && vault.height() <= h)
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let vault = &candidates[rand.next_in_range(0, candidates.len() as u64) as usize];
+    (rand.next_in_range(0, vault.rarity) == 0).then(|| vault.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vault_file_parses() {
+        let vaults = parse_vaults(VAULTS).unwrap();
+        assert!(!vaults.is_empty());
+        for vault in &vaults {
+            assert!(!vault.name.is_empty());
+            assert!(vault.min_depth <= vault.max_depth, "{}", vault.name);
+            assert!(
+                vault.cells.iter().flatten().any(|c| *c == '+'),
+                "{} has no door",
+                vault.name
+            );
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let vault = parse_vaults("name: test\nab\nmg\nwo\n").unwrap().remove(0);
+        let rotated = vault.transformed(1, false);
+        assert_eq!(3, rotated.width());
+        assert_eq!(2, rotated.height());
+        assert_eq!(vec![vec!['w', 'm', 'a'], vec!['o', 'g', 'b']], rotated.cells);
+        assert_eq!(vault.cells, vault.transformed(4, false).cells);
+        let mirrored = vault.transformed(0, true);
+        assert_eq!(vec!['b', 'a'], mirrored.cells[0]);
+    }
+}
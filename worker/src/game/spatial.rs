@@ -0,0 +1,144 @@
+//! Lookup of entities by position.
+//! The index lives in the [TileMap] and is rebuilt by [TileMap::update_caches].
+//! In between it's kept up to date by going through [move_actor] and friends.
+
+use std::collections::HashMap;
+
+use base::{Pos, zone};
+use froql::{entity_store::Entity, world::World};
+
+use crate::game::{
+    game_logic::{Actor, TileEffect},
+    tile_map::TileMap,
+};
+
+/// Kinds of entities that are looked up separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(unused)] // nothing lives on the items, decor and triggers layers yet
+pub enum Layer {
+    Actors,
+    Effects,
+    Items,
+    Decor,
+    Triggers,
+}
+
+const LAYER_COUNT: usize = 5;
+
+/// Entities by position, every position can hold any number of entities per layer.
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    layers: [HashMap<Pos, Vec<Entity>>; LAYER_COUNT],
+}
+
+impl SpatialIndex {
+    pub fn clear(&mut self, layer: Layer) {
+        self.layers[layer as usize].clear();
+    }
+
+    pub fn insert(&mut self, layer: Layer, pos: Pos, e: Entity) {
+        self.layers[layer as usize].entry(pos).or_default().push(e);
+    }
+
+    pub fn remove(&mut self, layer: Layer, pos: Pos, e: Entity) {
+        let cells = &mut self.layers[layer as usize];
+        if let Some(entities) = cells.get_mut(&pos) {
+            entities.retain(|other| *other != e);
+            if entities.is_empty() {
+                cells.remove(&pos);
+            }
+        }
+    }
+
+    pub fn move_entity(&mut self, layer: Layer, e: Entity, from: Pos, to: Pos) {
+        self.remove(layer, from, e);
+        self.insert(layer, to, e);
+    }
+
+    /// All entities on `pos`, in the order they were added.
+    pub fn at(&self, layer: Layer, pos: Pos) -> &[Entity] {
+        self.layers[layer as usize].get(&pos).map_or(&[], |entities| entities.as_slice())
+    }
+
+    /// The entity that was added first to `pos`.
+    pub fn first(&self, layer: Layer, pos: Pos) -> Option<Entity> {
+        self.at(layer, pos).first().copied()
+    }
+
+    /// All entities at most `radius` away from `center`, `center` included.
+    pub fn within(
+        &self,
+        layer: Layer,
+        center: Pos,
+        radius: i32,
+    ) -> impl Iterator<Item = (Pos, Entity)> {
+        ((center.y - radius)..=(center.y + radius))
+            .flat_map(move |y| {
+                ((center.x - radius)..=(center.x + radius)).map(move |x| Pos::new(x, y))
+            })
+            .flat_map(move |pos| self.at(layer, pos).iter().map(move |e| (pos, *e)))
+    }
+}
+
+/// Moves the actor `e` to `to` and updates the index.
+/// `actor` is the component of `e`, passed in because callers usually hold it already.
+pub fn move_actor(world: &World, e: Entity, actor: &mut Actor, to: Pos) {
+    zone!();
+    let mut tm = world.singleton_mut::<TileMap>();
+    tm.index.move_entity(Layer::Actors, e, actor.pos, to);
+    actor.pos = to;
+}
+
+/// Destroys the tile effect on `pos`, if there is one.
+pub fn remove_effect(world: &mut World, pos: Pos) {
+    let old = world.singleton::<TileMap>().get_effect(pos);
+    if let Some(e) = old {
+        world.singleton_mut::<TileMap>().index.remove(Layer::Effects, pos, e);
+        world.destroy(e);
+    }
+}
+
+/// Creates a tile effect on `pos` and adds it to the index.
+pub fn add_effect(world: &mut World, pos: Pos, effect: TileEffect) -> Entity {
+    let e = world.create().add(pos).add(effect).entity;
+    world.singleton_mut::<TileMap>().index.insert(Layer::Effects, pos, e);
+    e
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_test() {
+        let world = World::new();
+        let (a, b, c) = (world.create().entity, world.create().entity, world.create().entity);
+        let mut index = SpatialIndex::default();
+        let pos = Pos::new(3, 3);
+
+        index.insert(Layer::Actors, pos, a);
+        index.insert(Layer::Actors, pos, b);
+        index.insert(Layer::Effects, pos, c);
+        assert_eq!(&[a, b], index.at(Layer::Actors, pos));
+        assert_eq!(Some(c), index.first(Layer::Effects, pos));
+        assert!(index.at(Layer::Items, pos).is_empty());
+
+        index.move_entity(Layer::Actors, a, pos, Pos::new(5, 3));
+        assert_eq!(&[b], index.at(Layer::Actors, pos));
+        assert_eq!(Some(a), index.first(Layer::Actors, Pos::new(5, 3)));
+
+        index.remove(Layer::Actors, pos, b);
+        assert_eq!(None, index.first(Layer::Actors, pos));
+        // removing something that isn't there does nothing
+        index.remove(Layer::Actors, pos, b);
+
+        let near: Vec<_> = index.within(Layer::Actors, Pos::new(2, 2), 3).collect();
+        assert_eq!(vec![(Pos::new(5, 3), a)], near);
+        assert_eq!(0, index.within(Layer::Actors, Pos::new(2, 2), 2).count());
+        assert_eq!(1, index.within(Layer::Effects, pos, 0).count());
+
+        index.clear(Layer::Actors);
+        assert_eq!(None, index.first(Layer::Actors, Pos::new(5, 3)));
+        assert_eq!(Some(c), index.first(Layer::Effects, pos));
+    }
+}
@@ -1,6 +1,9 @@
-use base::{ContextTrait, FPos, Pos, Rect, zone};
+use base::{Color, ContextTrait, FPos, Pos, Rect, grids::Grid, zone};
 use quicksilver::Quicksilver;
 
+use super::autotile::{
+    self, BlobFamily, FloorFamily, NE, NW, S, SE, SW, WallFamily, neighbor_mask,
+};
 use super::z_levels::Z_SPRITE;
 
 pub const TILE_EXTRUSION: f32 = 1.;
@@ -8,20 +11,42 @@ pub const TILE_DIM: f32 = 32.;
 pub const TILE_SCALE: f32 = 2.0;
 pub const TILE_SIZE: f32 = TILE_DIM * TILE_SCALE;
 
+/// Darkens the rim of wall tops where they meet the floor
+const WALL_RIM: Color = Color { r: 0., g: 0., b: 0., a: 0.45 };
+/// The shadow walls cast on the floor next to them
+const WALL_SHADOW: Color = Color { r: 0., g: 0., b: 0., a: 0.3 };
+
 /// A graphical tile from the assets in 32rogues
 #[derive(Debug, Clone, Copy, Quicksilver)]
 #[repr(C)]
 pub enum DrawTile {
     Empty,
-    SkullWallTop,
-    SkullWallBot,
-    GrayFloor,
+    /// Any sprite from the tiles sheet, picked by autotiling
+    Tile {
+        x: i32,
+        y: i32,
+    },
+    /// Any sprite from the autotiles sheet
+    Autotile {
+        x: i32,
+        y: i32,
+    },
     DownStairs,
     UpStairs,
     Rock,
     Arrow,
     FireFlying,
     FireBurning,
+    Chasm,
+    DoorClosed,
+    DoorOpen,
+    Rubble,
+    Pillar,
+    Crate,
+    Barrel,
+    ExplosiveBarrel,
+    CoinsSmall,
+    CoinsLarge,
 }
 
 impl DrawTile {
@@ -29,15 +54,24 @@ impl DrawTile {
         zone!();
         let (asset, sx, sy) = match self {
             DrawTile::Empty => ("tiles", 0, 2),
-            DrawTile::SkullWallTop => ("tiles", 0, 5),
-            DrawTile::SkullWallBot => ("tiles", 1, 5),
-            DrawTile::GrayFloor => ("tiles", 0, 6),
+            DrawTile::Tile { x: sx, y: sy } => ("tiles", *sx, *sy),
+            DrawTile::Autotile { x: sx, y: sy } => ("autotiles", *sx, *sy),
             DrawTile::DownStairs => ("tiles", 7, 16),
             DrawTile::UpStairs => ("tiles", 8, 16),
             DrawTile::Rock => ("tiles", 1, 18),
             DrawTile::Arrow => ("items", 0, 23),
             DrawTile::FireFlying => ("animated-tiles", 0, 9),
             DrawTile::FireBurning => ("animated-tiles", 0, 8),
+            DrawTile::Chasm => ("tiles", 12, 16),
+            DrawTile::DoorClosed => ("tiles", 2, 16),
+            DrawTile::DoorOpen => ("tiles", 3, 16),
+            DrawTile::Rubble => ("tiles", 0, 18),
+            DrawTile::Pillar => ("tiles", 1, 4),
+            DrawTile::Crate => ("tiles", 0, 17),
+            DrawTile::Barrel => ("tiles", 4, 17),
+            DrawTile::ExplosiveBarrel => ("tiles", 2, 17),
+            DrawTile::CoinsSmall => ("items", 1, 24),
+            DrawTile::CoinsLarge => ("items", 2, 24),
         };
         let src = if asset == "tiles" {
             extruded_source((sx, sy))
@@ -55,25 +89,247 @@ pub enum LogicTile {
     Wall,
     Floor,
     Empty,
+    /// can't be walked on, but things can be pushed into it
+    Chasm,
+    /// puts out fires and swallows items
+    DeepWater,
+    /// blocks vision, walking into it opens it
+    DoorClosed,
+    DoorOpen,
+    /// slows down movement
+    Rubble,
+    Pillar,
+    /// props that break when something hits them
+    Crate,
+    Barrel,
+    /// goes up in flames when it breaks
+    ExplosiveBarrel,
+}
+
+impl LogicTile {
+    /// Actors can stand on this tile.
+    pub fn is_walkable(self) -> bool {
+        matches!(
+            self,
+            LogicTile::Floor | LogicTile::DeepWater | LogicTile::DoorOpen | LogicTile::Rubble
+        )
+    }
+
+    /// Actors can get through this tile, possibly after opening it.
+    pub fn is_passable(self) -> bool {
+        self.is_walkable() || self == LogicTile::DoorClosed
+    }
+
+    pub fn blocks_vision(self) -> bool {
+        matches!(self, LogicTile::Wall | LogicTile::DoorClosed | LogicTile::Pillar)
+    }
+
+    pub fn is_door(self) -> bool {
+        matches!(self, LogicTile::DoorClosed | LogicTile::DoorOpen)
+    }
+
+    /// Stuff standing around that breaks when walked into.
+    pub fn is_prop(self) -> bool {
+        matches!(self, LogicTile::Crate | LogicTile::Barrel | LogicTile::ExplosiveBarrel)
+    }
+
+    /// Name used in log messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            LogicTile::Wall | LogicTile::Empty => "wall",
+            LogicTile::Floor => "floor",
+            LogicTile::Chasm => "chasm",
+            LogicTile::DeepWater => "water",
+            LogicTile::DoorClosed | LogicTile::DoorOpen => "door",
+            LogicTile::Rubble => "rubble",
+            LogicTile::Pillar => "pillar",
+            LogicTile::Crate => "crate",
+            LogicTile::Barrel => "barrel",
+            LogicTile::ExplosiveBarrel => "oil barrel",
+        }
+    }
+
+    /// How much damage the tile takes before it breaks, 0 if it can't be broken.
+    pub fn base_integrity(self) -> i32 {
+        match self {
+            LogicTile::Wall => 3,
+            LogicTile::Pillar => 2,
+            LogicTile::Crate | LogicTile::Barrel | LogicTile::ExplosiveBarrel => 1,
+            _ => 0,
+        }
+    }
+
+    /// What is left after the tile breaks.
+    pub fn broken(self) -> LogicTile {
+        match self {
+            LogicTile::Wall
+            | LogicTile::Pillar
+            | LogicTile::DoorClosed
+            | LogicTile::DoorOpen => LogicTile::Rubble,
+            LogicTile::Crate | LogicTile::Barrel | LogicTile::ExplosiveBarrel => {
+                LogicTile::Floor
+            }
+            other => other,
+        }
+    }
+
+    /// Decor that is scattered around when the tile breaks.
+    pub fn debris(self) -> Option<Decor> {
+        match self {
+            LogicTile::Crate => Some(Decor::Debris1),
+            LogicTile::Barrel | LogicTile::ExplosiveBarrel => Some(Decor::Debris2),
+            _ => None,
+        }
+    }
+
+    /// Fire can burn on this tile.
+    pub fn can_burn(self) -> bool {
+        self.is_walkable() && self != LogicTile::DeepWater
+    }
+
+    /// Items put here stay where they are instead of sinking or falling.
+    pub fn can_hold_items(self) -> bool {
+        self.is_walkable() && self != LogicTile::DeepWater
+    }
+
+    /// Base time in aut it takes to step onto this tile.
+    pub fn move_cost(self) -> i64 {
+        match self {
+            LogicTile::Rubble => 20,
+            _ => 10,
+        }
+    }
+
+    /// The sprite of this tile has no background and needs a floor drawn below it.
+    pub fn is_on_floor(self) -> bool {
+        matches!(self, LogicTile::DoorClosed | LogicTile::DoorOpen | LogicTile::Rubble)
+            || self.is_prop()
+    }
 }
 
 #[derive(Debug, Clone, Copy, Quicksilver)]
 #[repr(C)]
 pub enum Environment {
     Catacomb,
+    GoblinCaves,
+    OrcFortress,
+    BurningForge,
 }
 
-pub fn generate_draw_tile(lt: LogicTile, env: Environment, below: LogicTile) -> DrawTile {
-    zone!();
-    match env {
-        Environment::Catacomb => match lt {
-            LogicTile::Wall => match below {
-                LogicTile::Wall => DrawTile::SkullWallTop,
-                _ => DrawTile::SkullWallBot,
+/// The sprite families an environment is drawn with.
+pub struct TileSet {
+    pub wall: WallFamily,
+    pub floor: FloorFamily,
+    pub liquid: BlobFamily,
+}
+
+impl Environment {
+    pub fn tile_set(self) -> TileSet {
+        match self {
+            Environment::Catacomb => TileSet {
+                wall: WallFamily::Skull,
+                floor: FloorFamily::Gray,
+                liquid: BlobFamily::Water,
+            },
+            Environment::GoblinCaves => TileSet {
+                wall: WallFamily::Dirt,
+                floor: FloorFamily::Dirt,
+                liquid: BlobFamily::Swamp,
             },
-            LogicTile::Floor => DrawTile::GrayFloor,
-            LogicTile::Empty => DrawTile::Empty,
-        },
+            Environment::OrcFortress => TileSet {
+                wall: WallFamily::StoneBrick,
+                floor: FloorFamily::Stone,
+                liquid: BlobFamily::Water,
+            },
+            Environment::BurningForge => TileSet {
+                wall: WallFamily::Igneous,
+                floor: FloorFamily::RedStone,
+                liquid: BlobFamily::Water,
+            },
+        }
+    }
+
+    /// Decor that mapgen scatters over the floor.
+    pub fn decor(self) -> &'static [Decor] {
+        match self {
+            Environment::Catacomb => &[Decor::Bones1, Decor::Bones2],
+            Environment::GoblinCaves => &[Decor::Mushrooms, Decor::Slime, Decor::Bones1],
+            Environment::OrcFortress => &[Decor::Debris1, Decor::LogPile, Decor::Bones2],
+            Environment::BurningForge => &[Decor::OreSack, Decor::LogPile, Decor::Debris2],
+        }
+    }
+}
+
+/// Picks the sprite for the tile at `pos`, walls and liquids depend on their neighbours.
+pub fn generate_draw_tile(tiles: &Grid<LogicTile>, pos: Pos, env: Environment) -> DrawTile {
+    zone!();
+    let set = env.tile_set();
+    match tiles[pos] {
+        LogicTile::Wall => {
+            let mask = neighbor_mask(tiles, pos, true, same_or_unknown(LogicTile::Wall));
+            let (x, y) = set.wall.sprite(mask, pos);
+            DrawTile::Tile { x, y }
+        }
+        LogicTile::Floor => generate_floor_tile(tiles, pos, env),
+        LogicTile::Empty => DrawTile::Empty,
+        LogicTile::Chasm => DrawTile::Chasm,
+        LogicTile::DeepWater => {
+            let mask = neighbor_mask(tiles, pos, false, same_or_unknown(LogicTile::DeepWater));
+            let (x, y) = set.liquid.sprite(mask, pos);
+            DrawTile::Autotile { x, y }
+        }
+        LogicTile::DoorClosed => DrawTile::DoorClosed,
+        LogicTile::DoorOpen => DrawTile::DoorOpen,
+        LogicTile::Rubble => DrawTile::Rubble,
+        LogicTile::Pillar => DrawTile::Pillar,
+        LogicTile::Crate => DrawTile::Crate,
+        LogicTile::Barrel => DrawTile::Barrel,
+        LogicTile::ExplosiveBarrel => DrawTile::ExplosiveBarrel,
+    }
+}
+
+/// Unexplored tiles count as part of every group of neighbours,
+/// so edges and borders don't give away the shape of terrain that wasn't seen yet.
+fn same_or_unknown(group: LogicTile) -> impl Fn(LogicTile) -> bool {
+    move |tile| tile == group || tile == LogicTile::Empty
+}
+
+/// The floor of the environment, also drawn below tiles that stand on the floor.
+/// Unexplored neighbours don't count as walls.
+pub fn generate_floor_tile(tiles: &Grid<LogicTile>, pos: Pos, env: Environment) -> DrawTile {
+    let mask = neighbor_mask(tiles, pos, true, |tile| tile != LogicTile::Wall);
+    let (x, y) = env.tile_set().floor.sprite(mask, pos);
+    DrawTile::Tile { x, y }
+}
+
+/// Draws the edges and corners where the tile at `pos` meets walls:
+/// a rim on wall tops and a shadow on everything else.
+pub fn draw_borders(c: &mut dyn ContextTrait, tiles: &Grid<LogicTile>, pos: Pos, z: i32) {
+    let (mask, color, width) = match tiles[pos] {
+        LogicTile::Empty => return,
+        LogicTile::Wall => {
+            let mask = neighbor_mask(tiles, pos, true, same_or_unknown(LogicTile::Wall));
+            // the side of the wall already shows where it ends at the bottom
+            (mask | S, WALL_RIM, 2. * TILE_SCALE)
+        }
+        _ => {
+            let mask = neighbor_mask(tiles, pos, true, |tile| tile != LogicTile::Wall);
+            (mask, WALL_SHADOW, 4. * TILE_SCALE)
+        }
+    };
+    let tile = pos_to_drawpos(pos).rect(TILE_SIZE);
+    for border in autotile::borders(mask) {
+        let rect = match border {
+            NE => tile.take_top(width).take_right(width),
+            SE => tile.take_bot(width).take_right(width),
+            SW => tile.take_bot(width).take_left(width),
+            NW => tile.take_top(width).take_left(width),
+            autotile::N => tile.take_top(width),
+            autotile::E => tile.take_right(width),
+            S => tile.take_bot(width),
+            _ => tile.take_left(width),
+        };
+        c.draw_rect(rect, color, z);
     }
 }
 
@@ -89,19 +345,46 @@ pub fn pos_to_drawpos(pos: Pos) -> FPos {
 pub enum Decor {
     BloodRed1,
     BloodRed2,
+    Bones1,
+    Bones2,
+    /// remains of broken props
+    Debris1,
+    Debris2,
+    Mushrooms,
+    Slime,
+    OreSack,
+    LogPile,
+    Pentagram,
+    Lamp,
+    Brazier,
 }
 
 impl Decor {
     pub fn draw(&self, c: &mut dyn ContextTrait, FPos { x, y }: FPos, z: i32) {
         zone!();
-        let src = match self {
-            Decor::BloodRed1 => (0, 22),
-            Decor::BloodRed2 => (1, 22),
+        let (asset, sx, sy) = match self {
+            Decor::BloodRed1 => ("tiles", 0, 22),
+            Decor::BloodRed2 => ("tiles", 1, 22),
+            Decor::Bones1 => ("tiles", 0, 21),
+            Decor::Bones2 => ("tiles", 1, 21),
+            Decor::Debris1 => ("tiles", 1, 17),
+            Decor::Debris2 => ("tiles", 3, 17),
+            Decor::Mushrooms => ("tiles", 0, 20),
+            Decor::Slime => ("tiles", 2, 22),
+            Decor::OreSack => ("tiles", 5, 17),
+            Decor::LogPile => ("tiles", 6, 17),
+            Decor::Pentagram => ("tiles", 14, 16),
+            Decor::Lamp => ("animated-tiles", 0, 7),
+            Decor::Brazier => ("animated-tiles", 0, 1),
         };
 
-        let src = extruded_source(src);
+        let src = if asset == "tiles" {
+            extruded_source((sx, sy))
+        } else {
+            Rect::new(sx as f32 * TILE_DIM, sy as f32 * TILE_DIM, TILE_DIM, TILE_DIM)
+        };
         let target = Rect::new(x, y, TILE_DIM * TILE_SCALE, TILE_DIM * TILE_SCALE);
-        c.draw_texture_part_scaled("tiles", src, target, z);
+        c.draw_texture_part_scaled(asset, src, target, z);
     }
 }
 
@@ -127,6 +410,10 @@ pub enum CreatureSprite {
     GoblinArcher,
     GoblinMage,
     OrcFighter,
+    OrcWizard,
+    OrcWarchief,
+    Troll,
+    Ettin,
 }
 
 impl CreatureSprite {
@@ -139,6 +426,10 @@ impl CreatureSprite {
             CreatureSprite::GoblinArcher => ("monsters", 5, 0),
             CreatureSprite::GoblinMage => ("monsters", 6, 0),
             CreatureSprite::OrcFighter => ("monsters", 3, 0),
+            CreatureSprite::OrcWizard => ("monsters", 1, 0),
+            CreatureSprite::OrcWarchief => ("monsters", 4, 0),
+            CreatureSprite::Troll => ("monsters", 2, 1),
+            CreatureSprite::Ettin => ("monsters", 0, 1),
         };
 
         let src = Rect::new(sx as f32 * TILE_DIM, sy as f32 * TILE_DIM, TILE_DIM, TILE_DIM);
@@ -1,42 +1,79 @@
-use std::collections::HashMap;
-
-use base::{Pos, grids::Grid};
+use base::{Pos, grids::Grid, zone};
 use froql::{entity_store::Entity, query, world::World};
 use quicksilver::Quicksilver;
 use quicksilver::empty::EmptyContainer;
 
 use super::{
     game_logic::TileEffect,
-    sprites::{Decor, LogicTile},
+    spatial::{Layer, SpatialIndex},
+    sprites::{Decor, Environment, LogicTile},
 };
-use crate::game::game_logic::Actor;
+use crate::game::game_logic::{Actor, CreatureType};
 
 #[derive(Debug, Quicksilver)]
 pub struct TileMap {
     pub tiles: Grid<LogicTile>,
-    #[quicksilver(skip)]
-    actors: HashMap<Pos, Entity>,
+    /// Decides how the level looks
+    pub environment: Environment,
+    /// Damage each tile can take before it breaks, 0 for unbreakable tiles
+    pub integrity: Grid<i32>,
+    /// Goes up whenever the terrain changes, so anything derived from it knows to update
+    pub revision: u32,
     pub decor: Vec<DecorWithPos>,
+    /// Gold lying around, picked up by walking over it
+    pub loot: Vec<Loot>,
     pub up_stairs: Pos,
     pub down_stairs: Pos,
     pub rooms: Vec<Room>,
+    /// Things mapgen wants placed on the level, e.g. the monsters of a vault
+    pub spawns: Vec<MapSpawn>,
+    /// Actors and tile effects by position
     #[quicksilver(skip)]
-    tile_effects: HashMap<Pos, Entity>,
+    pub index: SpatialIndex,
 }
 
 #[derive(Debug, Quicksilver)]
 pub struct DecorWithPos(pub Pos, pub Decor);
 
+#[derive(Debug, Quicksilver, Clone, Copy)]
+pub struct Loot {
+    pub pos: Pos,
+    pub gold: i32,
+}
+
+#[derive(Debug, Quicksilver, Clone, Copy)]
+pub struct MapSpawn {
+    pub pos: Pos,
+    pub kind: SpawnKind,
+}
+
+#[derive(Debug, Quicksilver, Clone, Copy)]
+pub enum SpawnKind {
+    Monster {
+        creature: CreatureType,
+    },
+    RandomMonster,
+    /// Turned into loot fitting the depth by mapgen
+    Item,
+    Fire,
+    /// Preferred position of the down stairs
+    DownStairs,
+}
+
 impl TileMap {
     pub fn new(w: i32, h: i32, start_tile: LogicTile) -> Self {
         Self {
             tiles: Grid::new(w, h, start_tile),
-            actors: HashMap::new(),
+            environment: Environment::Catacomb,
+            integrity: Grid::new(w, h, 0),
+            revision: 0,
             decor: Vec::new(),
+            loot: Vec::new(),
             up_stairs: Pos::new(0, 0),
             down_stairs: Pos::new(0, 0),
             rooms: Vec::new(),
-            tile_effects: HashMap::new(),
+            spawns: Vec::new(),
+            index: SpatialIndex::default(),
         }
     }
 
@@ -56,42 +93,62 @@ impl TileMap {
         }
     }
 
+    /// Changes the terrain during play.
+    pub fn set_tile(&mut self, pos: Pos, tile: LogicTile) {
+        self.tiles[pos] = tile;
+        self.revision += 1;
+    }
+
     pub fn add_decor(&mut self, pos: Pos, decor: Decor) {
         self.decor.push(DecorWithPos(pos, decor));
     }
 
+    /// Removes the loot at `pos` and returns how much gold it was worth.
+    pub fn take_loot(&mut self, pos: Pos) -> Option<i32> {
+        let index = self.loot.iter().position(|loot| loot.pos == pos)?;
+        Some(self.loot.swap_remove(index).gold)
+    }
+
     pub fn is_blocked(&self, pos: Pos) -> bool {
-        self.actors.contains_key(&pos) || self.is_wall(pos)
+        self.get_actor(pos).is_some() || !self.is_walkable(pos)
+    }
+
+    /// Terrain an actor can step on, ignoring other actors.
+    pub fn is_walkable(&self, pos: Pos) -> bool {
+        self.tiles.get_opt(pos).is_some_and(|tile| tile.is_walkable())
     }
 
-    pub fn is_wall(&self, pos: Pos) -> bool {
-        self.tiles.get_opt(pos).map(|tile| *tile == LogicTile::Wall).unwrap_or(false)
+    /// Terrain an actor can get through, closed doors included.
+    pub fn is_passable(&self, pos: Pos) -> bool {
+        self.tiles.get_opt(pos).is_some_and(|tile| tile.is_passable())
     }
 
     pub fn get_actor(&self, pos: Pos) -> Option<Entity> {
-        self.actors.get(&pos).copied()
+        self.index.first(Layer::Actors, pos)
     }
 
     pub fn get_effect(&self, pos: Pos) -> Option<Entity> {
-        self.tile_effects.get(&pos).copied()
+        self.index.first(Layer::Effects, pos)
     }
 
     pub fn blocks_vision(&self, pos: Pos) -> bool {
-        self.is_wall(pos)
+        self.tiles.get_opt(pos).is_some_and(|tile| tile.blocks_vision())
     }
 
-    /// Updates the cache of where actors are in the tilemap.
-    /// This is used for lookup of actors by position and pathfinding.
+    /// Rebuilds the index of where actors and tile effects are.
+    /// Only needed after entities were created or destroyed,
+    /// moves go through [move_actor](super::spatial::move_actor) and keep it up to date.
     pub fn update_caches(world: &mut World) {
+        zone!();
         let mut tm = world.singleton_mut::<TileMap>();
-        tm.actors.clear();
+        tm.index.clear(Layer::Actors);
         for (e, actor) in query!(world, &this, Actor) {
-            tm.actors.insert(actor.pos, *e);
+            tm.index.insert(Layer::Actors, actor.pos, *e);
         }
 
-        tm.tile_effects.clear();
+        tm.index.clear(Layer::Effects);
         for (e, pos) in query!(world, &this, Pos, _ TileEffect) {
-            tm.tile_effects.insert(*pos, *e);
+            tm.index.insert(Layer::Effects, *pos, *e);
         }
     }
 }
@@ -102,6 +159,20 @@ pub struct Room {
     pub y: i32,
     pub w: i32,
     pub h: i32,
+    pub role: RoomRole,
+}
+
+/// What a room is used for, decides what mapgen puts into it.
+#[derive(Debug, Quicksilver, Clone, Copy, PartialEq, Default)]
+#[repr(C)]
+pub enum RoomRole {
+    #[default]
+    Plain,
+    /// Holds a cache of loot
+    TreasureRoom,
+    /// Where the guards of a treasure room hang out
+    GuardPost,
+    Shrine,
 }
 
 impl Room {
@@ -111,6 +182,13 @@ impl Room {
         Pos { x, y }
     }
 
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.x <= pos.x
+            && pos.x < self.x + self.w
+            && self.y <= pos.y
+            && pos.y < self.y + self.h
+    }
+
     pub fn tile_count(&self) -> i32 {
         self.w * self.h
     }
@@ -130,10 +208,12 @@ mod test {
 
     #[test]
     fn room_test() {
-        let r = Room { x: 0, y: 0, w: 5, h: 3 };
+        let r = Room { x: 0, y: 0, w: 5, h: 3, role: RoomRole::Plain };
         assert_eq!(15, r.tile_count());
         assert_eq!(Pos::new(0, 0), r.tile_pos(0));
         assert_eq!(Pos::new(3, 0), r.tile_pos(3));
         assert_eq!(Pos::new(4, 2), r.tile_pos(14));
+        assert!(r.contains(Pos::new(4, 2)));
+        assert!(!r.contains(Pos::new(5, 2)));
     }
 }
@@ -1,8 +1,10 @@
 use super::InspectUIState;
 use super::ensure_singleton;
+use crate::game::PlayerAbility;
 use crate::game::UI;
 use crate::game::UIState;
-use crate::game::game_logic::{Actor, Player};
+use crate::game::dungeon::Dungeon;
+use crate::game::game_logic::{Actor, Player, RAGE_MAX};
 use crate::game::z_levels::*;
 use base::text::Labelize;
 use base::{Color, ContextTrait, Rect, TextProperty, text::TextFamily};
@@ -54,8 +56,31 @@ fn side_menu(c: &mut dyn ContextTrait, world: &mut World) {
         let current = actor.hp.current;
         let max = actor.hp.max;
         let pulse = player.pulse;
-        let text = format!("HP: {current}/{max}\nPulse: {pulse}");
-        let r = ui_rect.cut_top(100.).skip_left(10.);
+        let tier = player.tier.name();
+        let rage = player.rage;
+        let kills = player.kills;
+        let gold = player.gold;
+        let depth = world.singleton::<Dungeon>().depth;
+        let text = format!(
+            "Depth: {depth}\nHP: {current}/{max}\nPulse: {pulse} ({tier})\n\
+             Rage: {rage}/{RAGE_MAX}\nKills: {kills}\nGold: {gold}"
+        );
+        let r = ui_rect.cut_top(225.).skip_left(10.);
+        text.labelize(c, r.dim()).draw(c, r.origin(), Z_SIDEBAR_TEXT);
+
+        let now = actor.next_turn;
+        let mut text = String::from("Abilities:");
+        for (nr, ability) in PlayerAbility::HOTKEYS.into_iter().enumerate() {
+            let cost = ability.rage_cost();
+            let wait = player.ready_at(ability) - now;
+            let status = if wait > 0 {
+                format!("{:.1} turns", wait as f32 / 10.)
+            } else {
+                "ready".to_string()
+            };
+            text += &format!("\n{} {} ({cost}): {status}", nr + 1, ability.name());
+        }
+        let r = ui_rect.cut_top(200.).skip_left(10.);
         text.labelize(c, r.dim()).draw(c, r.origin(), Z_SIDEBAR_TEXT);
     }
 
@@ -1,13 +1,17 @@
 pub const Z_TILES: i32 = 0;
+pub const Z_TILE_BORDERS: i32 = 1;
 pub const Z_TILE_EFFECTS: i32 = 1;
+pub const Z_MEMORY: i32 = 2;
 pub const Z_HP_BAR: i32 = 9;
 pub const Z_SPRITE: i32 = 10;
+pub const Z_GHOST: i32 = 11;
 pub const Z_PROJECTILE: i32 = 15;
 pub const Z_DANGER_ZONE: i32 = 50;
 pub const Z_CURSOR: i32 = 100;
 #[allow(unused)]
 pub const Z_DEBUG: i32 = 999;
 pub const Z_AVY_LABEL: i32 = 1000;
+pub const Z_SCREEN_TINT: i32 = 1500;
 pub const Z_MESSAGE_BG: i32 = 2000;
 pub const Z_MESSAGE_TEXT: i32 = 2100;
 pub const Z_SIDEBAR_BG: i32 = 3000;
@@ -3,6 +3,7 @@ use std::{ffi::c_void, panic::AssertUnwindSafe};
 
 #[cfg(not(target_arch = "wasm32"))]
 use base::{ContextTrait, PersistWrapper};
+pub use game::mapgen::export as mapgen_export;
 pub use game::update_inner;
 pub use persistent::PersistentState;
 mod animation;